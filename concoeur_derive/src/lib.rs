@@ -0,0 +1,170 @@
+//! `#[derive(Component)]` and `#[derive(Resource)]` for `concoeur::ecs`; see
+//! `concoeur::ecs::component` for the attributes they take.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput, Ident};
+
+#[proc_macro_derive(Component, attributes(component))]
+pub fn derive_component(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_component(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(Resource, attributes(resource))]
+pub fn derive_resource(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_resource(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// What a `#[component(...)]` or `#[resource(...)]` attribute asks for.
+#[derive(Default)]
+struct Options {
+    storage: Option<Ident>,
+    persist: bool,
+    describe: bool,
+}
+
+impl Options {
+    /// Reads every `attribute` on `input`. Only components have a storage.
+    fn parse(input: &DeriveInput, attribute: &str) -> syn::Result<Self> {
+        let mut options = Options::default();
+        for attr in &input.attrs {
+            if !attr.path().is_ident(attribute) {
+                continue;
+            }
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("persist") {
+                    options.persist = true;
+                } else if meta.path.is_ident("describe") {
+                    options.describe = true;
+                } else if attribute == "component" && meta.path.is_ident("storage") {
+                    let kind: Ident = meta.value()?.parse()?;
+                    if kind != "Dense" && kind != "SparseSet" && kind != "Table" {
+                        return Err(syn::Error::new(
+                            kind.span(),
+                            "expected `Dense`, `SparseSet` or `Table`",
+                        ));
+                    }
+                    options.storage = Some(kind);
+                } else {
+                    return Err(meta.error(format!("unknown {} option", attribute)));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(options)
+    }
+}
+
+fn expand_component(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let options = Options::parse(input, "component")?;
+    let ident = &input.ident;
+    let name = ident.to_string();
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let storage = options.storage.map(|kind| {
+        quote! {
+            const STORAGE: ::concoeur::ecs::StorageKind = ::concoeur::ecs::StorageKind::#kind;
+        }
+    });
+    let persist = options
+        .persist
+        .then(|| quote!(world.register_saved_component::<Self>()?;));
+    let describe = options
+        .describe
+        .then(|| quote!(world.register_described::<Self>();));
+    Ok(quote! {
+        impl #impl_generics ::concoeur::ecs::Component for #ident #type_generics #where_clause {
+            const TYPE_NAME: &'static str = #name;
+            #storage
+
+            fn register(
+                world: &mut ::concoeur::ecs::World,
+            ) -> ::std::result::Result<(), ::concoeur::ecs::SaveError> {
+                world.register_component_with::<Self>(
+                    <Self as ::concoeur::ecs::Component>::STORAGE,
+                );
+                #persist
+                #describe
+                ::std::result::Result::Ok(())
+            }
+        }
+    })
+}
+
+fn expand_resource(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let options = Options::parse(input, "resource")?;
+    let ident = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let persist = options
+        .persist
+        .then(|| quote!(world.register_saved_resource::<Self>()?;));
+    let describe = options
+        .describe
+        .then(|| quote!(world.register_described::<Self>();));
+    Ok(quote! {
+        impl #impl_generics ::concoeur::ecs::Resource for #ident #type_generics #where_clause {
+            fn register(
+                world: &mut ::concoeur::ecs::World,
+            ) -> ::std::result::Result<(), ::concoeur::ecs::SaveError> {
+                #persist
+                #describe
+                ::std::result::Result::Ok(())
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use syn::parse_quote;
+
+    use super::*;
+
+    fn error(result: syn::Result<TokenStream2>) -> String {
+        result.err().unwrap().to_string()
+    }
+
+    #[test]
+    fn component_options() -> syn::Result<()> {
+        let input: DeriveInput = parse_quote! {
+            #[component(storage = SparseSet, persist)]
+            struct Player {}
+        };
+        let output = expand_component(&input)?.to_string();
+        assert!(output.contains("const TYPE_NAME : & 'static str = \"Player\""));
+        assert!(output.contains("StorageKind :: SparseSet"));
+        assert!(output.contains("register_saved_component"));
+        assert!(!output.contains("register_described"));
+
+        let input: DeriveInput = parse_quote!(
+            struct Position;
+        );
+        assert!(!expand_component(&input)?.to_string().contains("STORAGE :"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_options_are_errors() {
+        let input: DeriveInput = parse_quote! {
+            #[component(storage = Sparse)]
+            struct Player {}
+        };
+        assert_eq!(
+            error(expand_component(&input)),
+            "expected `Dense`, `SparseSet` or `Table`"
+        );
+
+        let input: DeriveInput = parse_quote! {
+            #[resource(storage = Dense)]
+            struct Map {}
+        };
+        assert_eq!(error(expand_resource(&input)), "unknown resource option");
+    }
+}
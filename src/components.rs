@@ -1,9 +1,12 @@
+use crate::ecs::{Component, Describe, Persist};
+
 pub struct Direction {
     pub x: i32,
     pub y: i32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Component)]
+#[component(persist, describe)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
@@ -12,19 +15,9 @@ pub struct Position {
 impl Position {
     fn add_signed_to_usize(u: usize, i: i32) -> usize {
         if i.is_negative() {
-            let option = u.checked_sub(i.wrapping_abs().try_into().unwrap());
-            if let Some(x) = option {
-                x
-            } else {
-                0
-            }
+            u.saturating_sub(i.wrapping_abs().try_into().unwrap())
         } else {
-            let option = u.checked_add(i as usize);
-            if let Some(x) = option {
-                x
-            } else {
-                0
-            }
+            u.checked_add(i as usize).unwrap_or_default()
         }
     }
 
@@ -33,7 +26,7 @@ impl Position {
         self.y = Self::add_signed_to_usize(self.y, dir.y);
     }
 
-    pub fn add_dir(&mut self, dir: &Direction) -> Self {
+    pub fn add_dir(&self, dir: &Direction) -> Self {
         Position {
             x: Self::add_signed_to_usize(self.x, dir.x),
             y: Self::add_signed_to_usize(self.y, dir.y),
@@ -41,9 +34,141 @@ impl Position {
     }
 }
 
+impl Persist for Position {
+    const NAME: &'static str = "Position";
+
+    fn save(&self) -> String {
+        format!("{} {}", self.x, self.y)
+    }
+
+    fn load(value: &str) -> Result<Self, String> {
+        let parse = |coordinate: Option<&str>| {
+            coordinate
+                .and_then(|coordinate| coordinate.parse().ok())
+                .ok_or_else(|| format!("invalid position {:?}", value))
+        };
+        let mut coordinates = value.split(' ');
+        Ok(Position {
+            x: parse(coordinates.next())?,
+            y: parse(coordinates.next())?,
+        })
+    }
+}
+
+impl Describe for Position {
+    fn describe(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+#[derive(Component)]
+#[component(persist, describe)]
 pub struct Renderable {
     pub display: char,
 }
 
-#[derive(Default)]
+impl Persist for Renderable {
+    const NAME: &'static str = "Renderable";
+
+    fn save(&self) -> String {
+        self.display.to_string()
+    }
+
+    fn load(value: &str) -> Result<Self, String> {
+        let mut chars = value.chars();
+        match (chars.next(), chars.next()) {
+            (Some(display), None) => Ok(Renderable { display }),
+            _ => Err(format!("expected one character, got {:?}", value)),
+        }
+    }
+}
+
+impl Describe for Renderable {
+    fn describe(&self) -> String {
+        format!("{:?}", self.display)
+    }
+}
+
+/// Drawing layer. Where entities share a tile, the one with the highest
+/// order is drawn; entities without one are drawn below all others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Component)]
+#[component(persist, describe)]
+pub struct RenderOrder(pub u8);
+
+impl Persist for RenderOrder {
+    const NAME: &'static str = "RenderOrder";
+
+    fn save(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn load(value: &str) -> Result<Self, String> {
+        value
+            .parse()
+            .map(RenderOrder)
+            .map_err(|_| format!("invalid render order {:?}", value))
+    }
+}
+
+impl Describe for RenderOrder {
+    fn describe(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+#[derive(Default, Component)]
+#[component(storage = SparseSet, persist)]
 pub struct Player {}
+
+impl Persist for Player {
+    const NAME: &'static str = "Player";
+
+    fn save(&self) -> String {
+        String::new()
+    }
+
+    fn load(_value: &str) -> Result<Self, String> {
+        Ok(Player {})
+    }
+}
+
+#[derive(Debug, PartialEq, Component)]
+#[component(persist, describe)]
+pub struct Name(pub String);
+
+impl Persist for Name {
+    const NAME: &'static str = "Name";
+
+    fn save(&self) -> String {
+        self.0.clone()
+    }
+
+    fn load(value: &str) -> Result<Self, String> {
+        if value.is_empty() || value.contains('\n') {
+            return Err("name has to be one non-empty line".to_string());
+        }
+        Ok(Name(value.to_string()))
+    }
+}
+
+impl Describe for Name {
+    fn describe(&self) -> String {
+        self.0.clone()
+    }
+}
+
+#[derive(Default, Component)]
+#[component(persist)]
+pub struct Monster {}
+
+impl Persist for Monster {
+    const NAME: &'static str = "Monster";
+
+    fn save(&self) -> String {
+        String::new()
+    }
+
+    fn load(_value: &str) -> Result<Self, String> {
+        Ok(Monster {})
+    }
+}
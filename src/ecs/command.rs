@@ -0,0 +1,112 @@
+use std::{cell::RefCell, fmt};
+
+use super::{Bundle, Component, EcsError, Entity, World};
+
+type Command = Box<dyn FnOnce(&mut World) -> Result<(), EcsError>>;
+
+/// Structural changes recorded through `&World`, e.g. while a query is
+/// borrowing the entities, and applied later by `World::apply_commands`.
+#[derive(Default)]
+pub struct Commands {
+    queue: RefCell<Vec<Command>>,
+}
+
+impl fmt::Debug for Commands {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Commands")
+            .field("len", &self.queue.borrow().len())
+            .finish()
+    }
+}
+
+impl Commands {
+    fn push(&self, command: impl FnOnce(&mut World) -> Result<(), EcsError> + 'static) {
+        self.queue.borrow_mut().push(Box::new(command));
+    }
+
+    /// Spawns an entity with the components added through the returned
+    /// builder.
+    pub fn spawn(&self) -> SpawnCommands<'_> {
+        SpawnCommands {
+            commands: self,
+            components: vec![],
+        }
+    }
+
+    pub fn spawn_bundle(&self, bundle: impl Bundle) {
+        self.push(move |world| world.spawn(bundle).map(|_| ()));
+    }
+
+    pub fn despawn(&self, entity: Entity) {
+        self.push(move |world| world.despawn(entity));
+    }
+
+    pub fn insert(&self, entity: Entity, data: impl Component) {
+        self.push(move |world| world.insert_component(entity, data));
+    }
+
+    /// Removes the `T` from `entity`. Applying it fails if there is none, or
+    /// if `World::remove_component` would leave it in place.
+    pub fn remove<T: Component>(&self, entity: Entity) {
+        self.push(move |world| {
+            if !world.check_remove::<T>(entity)? {
+                return Err(EcsError::ComponentMissing {
+                    entity,
+                    type_name: T::TYPE_NAME,
+                });
+            }
+            world.remove_component::<T>(entity);
+            Ok(())
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(super) fn take(&self) -> Vec<Command> {
+        std::mem::take(&mut self.queue.borrow_mut())
+    }
+}
+
+type Insert = Box<dyn FnOnce(&mut World, Entity) -> Result<(), EcsError>>;
+
+/// Builder returned by `Commands::spawn`. The spawn is recorded when it is
+/// dropped. Like `World::spawn`, hooks run once every component is in. If
+/// a component can't be inserted when it is applied, the entity is
+/// despawned again rather than left half-built.
+pub struct SpawnCommands<'a> {
+    commands: &'a Commands,
+    components: Vec<Insert>,
+}
+
+impl SpawnCommands<'_> {
+    pub fn with_component(mut self, data: impl Component) -> Self {
+        self.components.push(Box::new(move |world, entity| {
+            world.insert_unhooked(entity, data)
+        }));
+        self
+    }
+}
+
+impl Drop for SpawnCommands<'_> {
+    fn drop(&mut self) {
+        let components = std::mem::take(&mut self.components);
+        self.commands.push(move |world| {
+            let entity = world.create_entity().current_entity();
+            let inserted = components
+                .into_iter()
+                .try_for_each(|insert| insert(world, entity));
+            world.run_hooks();
+            // A hook may have despawned it already.
+            if inserted.is_err() && world.is_alive(entity) {
+                world.despawn(entity)?;
+            }
+            inserted
+        });
+    }
+}
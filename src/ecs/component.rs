@@ -0,0 +1,124 @@
+//! Traits behind `#[derive(Component)]` and `#[derive(Resource)]`, which
+//! bundle a type's registrations so setting one up is a single
+//! `World::register` or `World::register_resource` call:
+//!
+//! ```text
+//! #[derive(Component)]
+//! #[component(storage = SparseSet, persist, describe)]
+//! pub struct Player {}
+//! ```
+//!
+//! `storage` picks the `StorageKind` (dense by default), `persist` adds the
+//! type to saves through its `Persist` impl and `describe` adds its value to
+//! `World::dump` through its `Describe` impl.
+
+use std::any::Any;
+
+use super::{SaveError, StorageKind, World};
+
+pub trait Component: Any + Sized {
+    /// Type name without its module path, for diagnostics.
+    const TYPE_NAME: &'static str;
+
+    const STORAGE: StorageKind = StorageKind::Dense;
+
+    /// Registers the component plus whatever saving and inspection glue it
+    /// asked for.
+    fn register(world: &mut World) -> Result<(), SaveError> {
+        world.register_component_with::<Self>(Self::STORAGE);
+        Ok(())
+    }
+}
+
+pub trait Resource: Any + Sized {
+    /// Registers whatever saving and inspection glue the resource asked for.
+    fn register(_world: &mut World) -> Result<(), SaveError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ecs::{Component, Describe, Persist, Resource};
+
+    use super::*;
+
+    #[derive(Component)]
+    #[component(storage = SparseSet, persist, describe)]
+    struct Gold(pub u32);
+
+    #[derive(Component)]
+    struct Marker;
+
+    #[derive(Resource)]
+    #[resource(persist)]
+    struct Turn(pub u32);
+
+    impl Persist for Gold {
+        const NAME: &'static str = "Gold";
+
+        fn save(&self) -> String {
+            self.0.to_string()
+        }
+
+        fn load(value: &str) -> Result<Self, String> {
+            value.parse().map(Gold).map_err(|err| err.to_string())
+        }
+    }
+
+    impl Describe for Gold {
+        fn describe(&self) -> String {
+            format!("{} gold", self.0)
+        }
+    }
+
+    impl Persist for Turn {
+        const NAME: &'static str = "Turn";
+
+        fn save(&self) -> String {
+            self.0.to_string()
+        }
+
+        fn load(value: &str) -> Result<Self, String> {
+            value.parse().map(Turn).map_err(|err| err.to_string())
+        }
+    }
+
+    #[test]
+    fn derived_registrations() {
+        let mut world = World::new();
+        world.register::<Gold>().unwrap();
+        world.register::<Marker>().unwrap();
+        world.register_resource::<Turn>().unwrap();
+        world.add_resource(Turn(4));
+        assert_eq!(world.storage_kind::<Gold>(), Some(StorageKind::SparseSet));
+        assert_eq!(world.storage_kind::<Marker>(), Some(StorageKind::Dense));
+        assert_eq!(Gold::TYPE_NAME, "Gold");
+
+        world
+            .create_entity()
+            .with_component(Gold(7))
+            .unwrap()
+            .with_component(Marker)
+            .unwrap();
+        let mut saved = vec![];
+        world.save(&mut saved).unwrap();
+        assert_eq!(
+            String::from_utf8(saved).unwrap(),
+            "concoeur-save 1\nspawned 1\nresource Turn\n  4\nentity 0 0 0\ncomponent Gold\n  7\n"
+        );
+        let mut dump = vec![];
+        world.dump(&mut dump).unwrap();
+        assert!(String::from_utf8(dump)
+            .unwrap()
+            .contains("Gold\n  7 gold\n"));
+    }
+
+    #[test]
+    fn spawning_uses_the_derived_storage() {
+        let mut world = World::new();
+        world.spawn((Gold(1), Marker)).unwrap();
+        assert_eq!(world.storage_kind::<Gold>(), Some(StorageKind::SparseSet));
+        assert_eq!(world.storage_kind::<Marker>(), Some(StorageKind::Dense));
+    }
+}
@@ -1,66 +1,649 @@
+pub mod bitmask;
+pub mod bundle;
+pub mod entity_ref;
+pub mod hierarchy;
 pub mod query;
+pub mod storage;
+pub mod table;
+pub mod transfer;
+pub mod typed_query;
 
 use std::{
     any::{Any, TypeId},
-    cell::RefCell,
-    collections::HashMap,
+    cell::{BorrowError, BorrowMutError, Ref, RefCell, RefMut},
+    collections::{HashMap, HashSet},
+    fmt,
     rc::Rc,
 };
 
-pub type Component = Rc<RefCell<dyn Any>>;
-pub type Components = HashMap<TypeId, Vec<Option<Component>>>;
+use super::error::EcsError;
+use super::hook::{HookEvent, HookKind};
+use bitmask::BitMask;
+use bundle::Bundle;
+use storage::{ComponentStorage, ComponentTicks, Slot, StorageKind};
+use table::{Column, TableColumn, Tables};
+
+pub type Component = Rc<dyn ComponentCell>;
+pub type Components = HashMap<TypeId, ComponentStorage>;
+/// Deep-copies a component, e.g. for `World::snapshot`.
+pub type CloneComponent = fn(&dyn Any) -> Component;
+
+/// A `RefCell` around a component of any type, borrowed as `dyn Any`.
+/// Unlike `RefCell<dyn Any>` it can be turned back into the `RefCell<T>` it
+/// was made from, so the component can be moved out.
+pub trait ComponentCell {
+    fn try_borrow(&self) -> Result<Ref<'_, dyn Any>, BorrowError>;
+    fn try_borrow_mut(&self) -> Result<RefMut<'_, dyn Any>, BorrowMutError>;
+    fn into_any(self: Rc<Self>) -> Rc<dyn Any>;
+
+    /// Empty table column for the component's type.
+    fn new_column(&self) -> Box<dyn Column>;
+
+    fn borrow(&self) -> Ref<'_, dyn Any> {
+        self.try_borrow()
+            .expect("component already mutably borrowed")
+    }
+
+    fn borrow_mut(&self) -> RefMut<'_, dyn Any> {
+        self.try_borrow_mut().expect("component already borrowed")
+    }
+}
+
+impl<T: Any> ComponentCell for RefCell<T> {
+    fn try_borrow(&self) -> Result<Ref<'_, dyn Any>, BorrowError> {
+        RefCell::try_borrow(self).map(|value| Ref::map(value, |value| value as &dyn Any))
+    }
+
+    fn try_borrow_mut(&self) -> Result<RefMut<'_, dyn Any>, BorrowMutError> {
+        RefCell::try_borrow_mut(self).map(|value| RefMut::map(value, |value| value as &mut dyn Any))
+    }
+
+    fn into_any(self: Rc<Self>) -> Rc<dyn Any> {
+        self
+    }
+
+    fn new_column(&self) -> Box<dyn Column> {
+        Box::new(TableColumn::<T>::new())
+    }
+}
+
+impl fmt::Debug for dyn ComponentCell + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_borrow() {
+            Ok(value) => value.fmt(f),
+            Err(_) => f.write_str("<mutably borrowed>"),
+        }
+    }
+}
+
+/// Handle to an entity slot. The generation is bumped every time the slot is
+/// despawned, so handles kept around after a despawn are detected as stale
+/// instead of aliasing whatever entity reuses the slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Entity {
+    pub index: usize,
+    pub generation: u32,
+}
 
 #[derive(Debug, Default)]
 pub struct Entities {
     components: Components,
-    bit_masks: HashMap<TypeId, u32>,
-    map: Vec<u32>,
+    tables: Tables,
+    bits: HashMap<TypeId, usize>,
+    names: HashMap<TypeId, &'static str>,
+    map: Vec<BitMask>,
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free_indexes: Vec<usize>,
     inserting_into_index: usize,
+    change_tick: u32,
+    removed: HashMap<TypeId, Vec<Entity>>,
+    parents: Vec<Option<Entity>>,
+    children: Vec<Vec<Entity>>,
+    hooked: HashSet<TypeId>,
+    hook_events: Vec<HookEvent>,
+    /// Per slot, when its current entity was created, counting up from 0.
+    spawn_sequence: Vec<u64>,
+    spawn_count: u64,
 }
 
 impl Entities {
-    pub fn register_component<T: Any + 'static>(&mut self) {
-        let type_id = TypeId::of::<T>();
-        self.components.insert(type_id, vec![]);
-        self.bit_masks.insert(type_id, 1 << self.bit_masks.len());
+    pub fn register_component<T: super::Component>(&mut self) {
+        self.register_component_with::<T>(StorageKind::Dense);
+    }
+
+    pub fn register_component_with<T: super::Component>(&mut self, kind: StorageKind) {
+        self.register_raw(TypeId::of::<T>(), kind, T::TYPE_NAME);
+    }
+
+    /// `register_component_with` for a type only known by its id.
+    fn register_raw(&mut self, type_id: TypeId, kind: StorageKind, type_name: &'static str) {
+        if self.bits.contains_key(&type_id) {
+            return;
+        }
+        self.components
+            .insert(type_id, ComponentStorage::new(kind, self.map.len()));
+        self.bits.insert(type_id, self.bits.len());
+        self.names.insert(type_id, type_name);
+    }
+
+    /// Starts recording `HookEvent`s for `type_id` when it is added or
+    /// replaced. Removals are hooked by `World` before they happen.
+    pub(crate) fn watch(&mut self, type_id: TypeId) {
+        self.hooked.insert(type_id);
+    }
+
+    /// Hook events recorded since the last call, oldest first.
+    pub(crate) fn take_hook_events(&mut self) -> Vec<HookEvent> {
+        std::mem::take(&mut self.hook_events)
+    }
+
+    fn record(&mut self, kind: HookKind, type_id: TypeId, entity: Entity) {
+        if self.hooked.contains(&type_id) {
+            self.hook_events.push(HookEvent {
+                kind,
+                type_id,
+                entity,
+            });
+        }
+    }
+
+    pub fn storage_kind<T: super::Component>(&self) -> Option<StorageKind> {
+        self.components
+            .get(&TypeId::of::<T>())
+            .map(|components| components.kind())
     }
 
     pub fn create_entity(&mut self) -> &mut Self {
-        if let Some((index, _)) = self
-            .map
-            .iter()
-            .enumerate()
-            .find(|(_index, mask)| **mask == 0)
-        {
+        let sequence = self.next_spawn();
+        if let Some(index) = self.free_indexes.pop() {
+            self.alive[index] = true;
+            self.spawn_sequence[index] = sequence;
             self.inserting_into_index = index;
         } else {
-            self.components
-                .iter_mut()
-                .for_each(|(_key, components)| components.push(None));
-            self.map.push(0);
-            self.inserting_into_index = self.map.len() - 1;
+            self.inserting_into_index = self.push_slot(0, Some(sequence)).index;
         }
         self
     }
 
-    pub fn with_component(&mut self, data: impl Any) -> Result<&mut Self, &'static str> {
-        let type_id = data.type_id();
+    fn next_spawn(&mut self) -> u64 {
+        self.spawn_count += 1;
+        self.spawn_count - 1
+    }
+
+    /// Position of `entity` among all entities ever created, oldest first.
+    /// Unlike slot indexes it doesn't change when slots are recycled, so
+    /// queries yield entities in this order.
+    pub fn spawn_sequence(&self, entity: Entity) -> Option<u64> {
+        self.is_alive(entity)
+            .then(|| self.spawn_sequence[entity.index])
+    }
+
+    /// Number of entities ever created, which is the spawn sequence the
+    /// next one gets.
+    pub(crate) fn spawn_count(&self) -> u64 {
+        self.spawn_count
+    }
+
+    pub(crate) fn set_spawn_count(&mut self, count: u64) {
+        self.spawn_count = count;
+    }
+
+    /// Spawns an entity with every component in `bundle`, registering the
+    /// ones that aren't registered yet. The bundle is checked before the
+    /// entity is created, so a failure doesn't leave a half-built entity.
+    pub fn spawn<B: Bundle>(&mut self, bundle: B) -> Result<Entity, EcsError> {
+        bundle::check::<B>()?;
+        let entity = self.create_entity().current_entity();
+        self.insert_bundle(entity, bundle)?;
+        Ok(entity)
+    }
+
+    /// Adds every component in `bundle` to `entity`, replacing ones it
+    /// already has and registering the ones that aren't registered yet.
+    pub fn insert_bundle<B: Bundle>(&mut self, entity: Entity, bundle: B) -> Result<(), EcsError> {
+        bundle::check::<B>()?;
+        if !self.is_alive(entity) {
+            return Err(EcsError::StaleEntity { entity });
+        }
+        B::register(self);
+        bundle.insert(self, entity)
+    }
+
+    /// Handle of the entity that `with_component` is currently inserting into.
+    pub fn current_entity(&self) -> Entity {
+        self.entity_at(self.inserting_into_index)
+    }
+
+    /// Despawns `entity` and all of its descendants.
+    pub fn despawn(&mut self, entity: Entity) -> Result<(), EcsError> {
+        if !self.is_alive(entity) {
+            return Err(EcsError::StaleEntity { entity });
+        }
+        self.remove_parent(entity);
+        for descendant in self.descendants(entity) {
+            self.despawn_one(descendant);
+        }
+        self.despawn_one(entity);
+        Ok(())
+    }
+
+    fn despawn_one(&mut self, entity: Entity) {
+        let index = entity.index;
+        self.parents[index] = None;
+        self.children[index].clear();
+        for (type_id, components) in self.components.iter_mut() {
+            if components.remove(index).is_some() {
+                self.removed.entry(*type_id).or_default().push(entity);
+            }
+        }
+        for type_id in self.tables.remove_all(index) {
+            self.removed.entry(type_id).or_default().push(entity);
+        }
+        self.map[index].clear();
+        self.alive[index] = false;
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.free_indexes.push(index);
+    }
+
+    /// Copy of every entity slot and relation, with deep copies of the
+    /// components that have an entry in `cloners`. Other components are left
+    /// out.
+    pub(crate) fn clone_with(
+        &self,
+        cloners: &HashMap<TypeId, CloneComponent>,
+    ) -> Result<Entities, EcsError> {
+        let mut map = self.map.clone();
+        let mut components = Components::new();
+        let mut tables = Tables::default();
+        (0..self.map.len()).for_each(|_| tables.push_slot());
+        for (type_id, storage) in &self.components {
+            let mut cloned = ComponentStorage::new(storage.kind(), self.map.len());
+            let Some(clone) = cloners.get(type_id) else {
+                map.iter_mut()
+                    .for_each(|entity_map| entity_map.remove(self.bits[type_id]));
+                components.insert(*type_id, cloned);
+                continue;
+            };
+            for index in 0..self.map.len() {
+                let Some((cell, ticks)) = self.get_cell(type_id, index) else {
+                    continue;
+                };
+                let component = cell.try_borrow().map_err(|_| EcsError::AlreadyBorrowed {
+                    entity: self.entity_at(index),
+                    type_name: self.names[type_id],
+                })?;
+                let slot = Slot {
+                    component: clone(&*component),
+                    ticks: ticks.clone(),
+                };
+                match cloned {
+                    ComponentStorage::Table => tables.insert_slot(index, *type_id, slot),
+                    _ => cloned.insert_slot(index, slot),
+                }
+            }
+            components.insert(*type_id, cloned);
+        }
+        Ok(Entities {
+            components,
+            tables,
+            bits: self.bits.clone(),
+            names: self.names.clone(),
+            map,
+            generations: self.generations.clone(),
+            alive: self.alive.clone(),
+            free_indexes: self.free_indexes.clone(),
+            inserting_into_index: self.inserting_into_index,
+            change_tick: self.change_tick,
+            removed: HashMap::new(),
+            parents: self.parents.clone(),
+            children: self.children.clone(),
+            hooked: self.hooked.clone(),
+            hook_events: vec![],
+            spawn_sequence: self.spawn_sequence.clone(),
+            spawn_count: self.spawn_count,
+        })
+    }
+
+    /// Replaces every entity with a copy of the ones in `snapshot`, made by
+    /// `clone_with`. Registrations and hooks added since are kept.
+    pub(crate) fn restore(
+        &mut self,
+        snapshot: &Entities,
+        cloners: &HashMap<TypeId, CloneComponent>,
+    ) {
+        let mut restored = snapshot
+            .clone_with(cloners)
+            .expect("snapshot components are never borrowed");
+        for (type_id, storage) in &self.components {
+            if !restored.components.contains_key(type_id) {
+                let bit = restored.bits.len();
+                restored.components.insert(
+                    *type_id,
+                    ComponentStorage::new(storage.kind(), restored.map.len()),
+                );
+                restored.bits.insert(*type_id, bit);
+                restored.names.insert(*type_id, self.names[type_id]);
+            }
+        }
+        restored.hooked = std::mem::take(&mut self.hooked);
+        *self = restored;
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.alive.get(entity.index).copied().unwrap_or(false)
+            && self.generations[entity.index] == entity.generation
+    }
+
+    /// Every entity slot, alive or not, with its current handle.
+    pub fn slots(&self) -> impl Iterator<Item = (Entity, bool)> + '_ {
+        (0..self.map.len()).map(|index| (self.entity_at(index), self.alive[index]))
+    }
+
+    /// Appends a slot with the given generation, for rebuilding a world slot
+    /// by slot. Live slots need their spawn sequence; dead ones go on the
+    /// free list.
+    pub(crate) fn push_slot(&mut self, generation: u32, sequence: Option<u64>) -> Entity {
+        let alive = sequence.is_some();
+        let index = self.map.len();
+        self.components
+            .values_mut()
+            .for_each(|components| components.push_empty());
+        self.tables.push_slot();
+        self.map.push(BitMask::default());
+        self.generations.push(generation);
+        self.alive.push(alive);
+        self.parents.push(None);
+        self.children.push(vec![]);
+        self.spawn_sequence.push(sequence.unwrap_or_default());
+        if !alive {
+            self.free_indexes.push(index);
+        }
+        Entity { index, generation }
+    }
+
+    /// Dead slots, in the order `create_entity` reuses them.
+    pub(crate) fn free_slots(&self) -> impl Iterator<Item = usize> + '_ {
+        self.free_indexes.iter().rev().copied()
+    }
+
+    /// Makes `create_entity` reuse dead slots in `order`. Changes nothing
+    /// and returns false unless `order` lists every dead slot once.
+    pub(crate) fn set_free_slots(&mut self, order: &[usize]) -> bool {
+        let mut sorted = order.to_vec();
+        sorted.sort_unstable();
+        let mut dead = self.free_indexes.clone();
+        dead.sort_unstable();
+        if sorted != dead {
+            return false;
+        }
+        self.free_indexes = order.iter().rev().copied().collect();
+        true
+    }
+
+    pub fn entity_at(&self, index: usize) -> Entity {
+        Entity {
+            index,
+            generation: self.generations[index],
+        }
+    }
+
+    pub fn with_component<T: super::Component>(&mut self, data: T) -> Result<&mut Self, EcsError> {
         let index = self.inserting_into_index;
-        if let Some(components) = self.components.get_mut(&type_id) {
-            let component = components
-                .get_mut(index)
-                .ok_or("Create component never called")?;
-            *component = Some(Rc::new(RefCell::new(data)));
-            let bitmask = self.bit_masks.get(&type_id).unwrap();
-            self.map[index] |= *bitmask;
-        } else {
-            return Err("Component not registered");
+        if index >= self.map.len() {
+            return Err(EcsError::NoEntityBeingCreated);
         }
+        self.insert_component(self.entity_at(index), data)?;
         Ok(self)
     }
 
-    pub fn get_bitmask(&self, type_id: &TypeId) -> Option<u32> {
-        self.bit_masks.get(&type_id).copied()
+    pub fn insert_component<T: super::Component>(
+        &mut self,
+        entity: Entity,
+        data: T,
+    ) -> Result<(), EcsError> {
+        if !self.is_alive(entity) {
+            return Err(EcsError::StaleEntity { entity });
+        }
+        let type_id = TypeId::of::<T>();
+        if !self.components.contains_key(&type_id) {
+            return Err(EcsError::ComponentNotRegistered {
+                type_name: T::TYPE_NAME,
+            });
+        }
+        self.insert_raw(entity, type_id, Rc::new(RefCell::new(data)));
+        Ok(())
+    }
+
+    /// `insert_component` for a live entity and a registered type.
+    fn insert_raw(&mut self, entity: Entity, type_id: TypeId, component: Component) {
+        match self.components.get_mut(&type_id).unwrap() {
+            ComponentStorage::Table => {
+                self.tables
+                    .insert(entity.index, type_id, component, self.change_tick)
+            }
+            components => components.insert(entity.index, component, self.change_tick),
+        }
+        let bit = self.bits[&type_id];
+        let kind = if self.map[entity.index].contains(bit) {
+            HookKind::Replace
+        } else {
+            HookKind::Add
+        };
+        self.map[entity.index].insert(bit);
+        self.record(kind, type_id, entity);
+    }
+
+    /// Takes the component off the entity. Returns `None` if the entity is
+    /// stale or has no `T`, and leaves the component in place if it is still
+    /// shared by the result of a `Query::run`; `check_remove` tells these
+    /// apart.
+    pub fn remove_component<T: super::Component>(&mut self, entity: Entity) -> Option<T> {
+        if !self.check_remove::<T>(entity).unwrap_or(false) {
+            return None;
+        }
+        self.remove_raw(entity, TypeId::of::<T>())?
+            .into_any()
+            .downcast::<RefCell<T>>()
+            .ok()
+            .and_then(|component| Rc::try_unwrap(component).ok())
+            .map(RefCell::into_inner)
+    }
+
+    /// `remove_component` for a live entity and a registered type, without
+    /// the check for a `Query::run` still sharing the component.
+    fn remove_raw(&mut self, entity: Entity, type_id: TypeId) -> Option<Component> {
+        let component = match self.components.get_mut(&type_id).unwrap() {
+            ComponentStorage::Table => self.tables.remove(entity.index, type_id),
+            components => components.remove(entity.index),
+        }?;
+        self.map[entity.index].remove(self.bits[&type_id]);
+        self.removed.entry(type_id).or_default().push(entity);
+        Some(component)
+    }
+
+    /// Whether `remove_component` would take a `T` off `entity`, or the
+    /// error it would return.
+    pub(crate) fn check_remove<T: super::Component>(
+        &self,
+        entity: Entity,
+    ) -> Result<bool, EcsError> {
+        if !self.is_alive(entity) {
+            return Err(EcsError::StaleEntity { entity });
+        }
+        if !self.has_component::<T>(entity) {
+            return Ok(false);
+        }
+        let type_id = TypeId::of::<T>();
+        if self.is_shared(&type_id, entity.index) {
+            return Err(EcsError::AlreadyBorrowed {
+                entity,
+                type_name: self.names[&type_id],
+            });
+        }
+        Ok(true)
+    }
+
+    /// Whether the result of a `Query::run` still holds the component.
+    /// Table components are never handed out that way.
+    fn is_shared(&self, type_id: &TypeId, index: usize) -> bool {
+        self.components[type_id]
+            .get(index)
+            .is_some_and(|component| Rc::strong_count(component) > 1)
+    }
+
+    pub fn has_component<T: super::Component>(&self, entity: Entity) -> bool {
+        self.contains_type(entity, TypeId::of::<T>())
+    }
+
+    /// `has_component` for a type only known by its id.
+    pub(crate) fn contains_type(&self, entity: Entity, type_id: TypeId) -> bool {
+        self.is_alive(entity)
+            && self
+                .bits
+                .get(&type_id)
+                .is_some_and(|bit| self.map[entity.index].contains(*bit))
+    }
+
+    /// Type names of the components `entity` has, in registration order.
+    pub fn component_names(&self, entity: Entity) -> Vec<&'static str> {
+        self.components_of(entity)
+            .into_iter()
+            .map(|(_, name, _)| name)
+            .collect()
+    }
+
+    /// Type id, type name and value of every component `entity` has, in
+    /// registration order.
+    pub(crate) fn components_of(
+        &self,
+        entity: Entity,
+    ) -> Vec<(TypeId, &'static str, &dyn ComponentCell)> {
+        if !self.is_alive(entity) {
+            return vec![];
+        }
+        let mut bits: Vec<(usize, TypeId)> = self
+            .bits
+            .iter()
+            .filter(|(_, bit)| self.map[entity.index].contains(**bit))
+            .map(|(type_id, bit)| (*bit, *type_id))
+            .collect();
+        bits.sort_by_key(|(bit, _)| *bit);
+        bits.into_iter()
+            .filter_map(|(_, type_id)| {
+                let (component, _) = self.get_cell(&type_id, entity.index)?;
+                Some((type_id, self.names[&type_id], component))
+            })
+            .collect()
+    }
+
+    /// The component and its ticks, wherever its storage keeps them.
+    fn get_cell(
+        &self,
+        type_id: &TypeId,
+        index: usize,
+    ) -> Option<(&dyn ComponentCell, &ComponentTicks)> {
+        match self.components.get(type_id)? {
+            ComponentStorage::Table => self.tables.get(type_id, index),
+            components => components
+                .get_slot(index)
+                .map(|slot| (&*slot.component, &slot.ticks)),
+        }
+    }
+
+    fn cell_at<T: super::Component>(
+        &self,
+        index: usize,
+    ) -> Result<(&dyn ComponentCell, &ComponentTicks), EcsError> {
+        let type_name = T::TYPE_NAME;
+        let type_id = TypeId::of::<T>();
+        if !self.components.contains_key(&type_id) {
+            return Err(EcsError::ComponentNotRegistered { type_name });
+        }
+        self.get_cell(&type_id, index)
+            .ok_or(EcsError::ComponentMissing {
+                entity: self.entity_at(index),
+                type_name,
+            })
+    }
+
+    pub(crate) fn borrow_at<T: super::Component>(
+        &self,
+        index: usize,
+    ) -> Result<Ref<'_, T>, EcsError> {
+        let (component, _) = self.cell_at::<T>(index)?;
+        let component = component
+            .try_borrow()
+            .map_err(|_| EcsError::AlreadyBorrowed {
+                entity: self.entity_at(index),
+                type_name: T::TYPE_NAME,
+            })?;
+        Ok(Ref::map(component, |any| any.downcast_ref::<T>().unwrap()))
+    }
+
+    /// Like `borrow_at`, but also marks the component as changed.
+    pub(crate) fn borrow_mut_at<T: super::Component>(
+        &self,
+        index: usize,
+    ) -> Result<RefMut<'_, T>, EcsError> {
+        let (component, ticks) = self.cell_at::<T>(index)?;
+        let component = component
+            .try_borrow_mut()
+            .map_err(|_| EcsError::AlreadyBorrowed {
+                entity: self.entity_at(index),
+                type_name: T::TYPE_NAME,
+            })?;
+        ticks.set_changed(self.change_tick);
+        Ok(RefMut::map(component, |any| {
+            any.downcast_mut::<T>().unwrap()
+        }))
+    }
+
+    pub fn get_bitmask(&self, type_id: &TypeId) -> Option<BitMask> {
+        self.bits.get(type_id).map(|bit| BitMask::from_bit(*bit))
+    }
+
+    /// Tick that inserts and mutable borrows are stamped with. It only
+    /// moves forward in `clear_trackers`, so "changed" means changed since
+    /// the last call to `clear_trackers`.
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick
+    }
+
+    pub fn get_ticks(&self, type_id: &TypeId, index: usize) -> Option<&ComponentTicks> {
+        self.get_cell(type_id, index).map(|(_, ticks)| ticks)
+    }
+
+    pub(crate) fn tables(&self) -> &Tables {
+        &self.tables
+    }
+
+    pub fn is_added<T: super::Component>(&self, entity: Entity) -> bool {
+        self.is_alive(entity)
+            && self
+                .get_ticks(&TypeId::of::<T>(), entity.index)
+                .is_some_and(|ticks| ticks.added == self.change_tick)
+    }
+
+    pub fn is_changed<T: super::Component>(&self, entity: Entity) -> bool {
+        self.is_alive(entity)
+            && self
+                .get_ticks(&TypeId::of::<T>(), entity.index)
+                .is_some_and(|ticks| ticks.changed.get() == self.change_tick)
+    }
+
+    /// Entities that lost a `T`, by removal or despawn, since the last call to
+    /// `clear_trackers`.
+    pub fn removed<T: super::Component>(&self) -> &[Entity] {
+        self.removed
+            .get(&TypeId::of::<T>())
+            .map(|removed| removed.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn clear_trackers(&mut self) {
+        self.change_tick = self.change_tick.wrapping_add(1);
+        self.removed.clear();
     }
 }
 
@@ -69,18 +652,29 @@ mod test {
 
     use std::any::TypeId;
 
-    use super::Entities;
+    use crate::ecs::Component;
 
+    use super::{BitMask, ComponentStorage, EcsError, Entities, Entity, StorageKind};
+
+    #[derive(Component)]
     struct Health(pub u32);
+    #[derive(Component)]
     struct Speed(pub u32);
 
+    fn slot_count(components: &ComponentStorage) -> usize {
+        match components {
+            ComponentStorage::Dense(components) => components.len(),
+            _ => panic!("expected dense storage"),
+        }
+    }
+
     #[test]
     fn register_an_entity() {
         let mut entities = Entities::default();
         entities.register_component::<Health>();
         let type_id = TypeId::of::<Health>();
         let health_components = entities.components.get(&type_id).unwrap();
-        assert_eq!(health_components.len(), 0)
+        assert_eq!(slot_count(health_components), 0)
     }
 
     #[test]
@@ -88,13 +682,13 @@ mod test {
         let mut entities = Entities::default();
         entities.register_component::<Health>();
         let type_id = TypeId::of::<Health>();
-        let mask = entities.bit_masks.get(&type_id).unwrap();
-        assert_eq!(*mask, 0b0000_0001);
+        let mask = entities.get_bitmask(&type_id).unwrap();
+        assert_eq!(mask, BitMask::from_bit(0));
 
         entities.register_component::<Speed>();
         let type_id = TypeId::of::<Speed>();
-        let mask = entities.bit_masks.get(&type_id).unwrap();
-        assert_eq!(*mask, 0b0000_0010);
+        let mask = entities.get_bitmask(&type_id).unwrap();
+        assert_eq!(mask, BitMask::from_bit(1));
     }
 
     #[test]
@@ -108,12 +702,15 @@ mod test {
         let health_components = entities.components.get(&TypeId::of::<Health>()).unwrap();
         let speed_components = entities.components.get(&TypeId::of::<Speed>()).unwrap();
 
-        assert!(health_components.len() == speed_components.len() && health_components.len() == 1);
-        assert!(health_components[0].is_none() && speed_components[0].is_none());
+        assert!(
+            slot_count(health_components) == slot_count(speed_components)
+                && slot_count(health_components) == 3
+        );
+        assert!(health_components.get(0).is_none() && speed_components.get(0).is_none());
     }
 
     #[test]
-    fn with_component() -> Result<(), &'static str> {
+    fn with_component() -> Result<(), EcsError> {
         let mut entities = Entities::default();
         entities.register_component::<Health>();
         entities.register_component::<Speed>();
@@ -122,14 +719,14 @@ mod test {
             .with_component(Health(100))?
             .with_component(Speed(50))?;
 
-        let first_health = &entities.components.get(&TypeId::of::<Health>()).unwrap()[0];
-        let wrapped_health = first_health.as_ref().unwrap();
+        let first_health = entities.components.get(&TypeId::of::<Health>()).unwrap();
+        let wrapped_health = first_health.get(0).unwrap();
         let borrowed_health = wrapped_health.borrow();
         let health = borrowed_health.downcast_ref::<Health>().unwrap();
         assert_eq!(health.0, 100);
 
-        let first_speed = &entities.components.get(&TypeId::of::<Speed>()).unwrap()[0];
-        let wrapped_speed = first_speed.as_ref().unwrap();
+        let first_speed = entities.components.get(&TypeId::of::<Speed>()).unwrap();
+        let wrapped_speed = first_speed.get(0).unwrap();
         let borrowed_speed = wrapped_speed.borrow();
         let speed = borrowed_speed.downcast_ref::<Speed>().unwrap();
         assert_eq!(speed.0, 50);
@@ -138,7 +735,7 @@ mod test {
     }
 
     #[test]
-    fn map_is_updated_when_creating_entities() -> Result<(), &'static str> {
+    fn map_is_updated_when_creating_entities() -> Result<(), EcsError> {
         let mut entities = Entities::default();
         entities.register_component::<Health>();
         entities.register_component::<Speed>();
@@ -146,17 +743,397 @@ mod test {
             .create_entity()
             .with_component(Health(100))?
             .with_component(Speed(15))?;
-        let entity_map = entities.map[0];
-        assert_eq!(entity_map, 3);
+        let entity_map = &entities.map[0];
+        assert_eq!(*entity_map, [0, 1].into_iter().collect());
 
         entities.create_entity().with_component(Speed(15))?;
-        let entity_map = entities.map[1];
-        assert_eq!(entity_map, 2);
+        let entity_map = &entities.map[1];
+        assert_eq!(*entity_map, BitMask::from_bit(1));
+
+        entities.create_entity().with_component(Health(40))?;
+        let entity_map = &entities.map[2];
+        assert_eq!(*entity_map, BitMask::from_bit(0));
+
+        Ok(())
+    }
 
+    #[test]
+    fn despawn_clears_components_and_map() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component::<Health>();
+        entities.register_component::<Speed>();
+        let entity = entities
+            .create_entity()
+            .with_component(Health(100))?
+            .with_component(Speed(15))?
+            .current_entity();
+
+        entities.despawn(entity)?;
+
+        assert!(entities.map[0].is_empty());
+        assert!(entities.components[&TypeId::of::<Health>()]
+            .get(0)
+            .is_none());
+        assert!(entities.components[&TypeId::of::<Speed>()].get(0).is_none());
+        assert!(!entities.is_alive(entity));
+
+        Ok(())
+    }
+
+    #[test]
+    fn recycled_slot_gets_new_generation() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component::<Health>();
+        let first = entities
+            .create_entity()
+            .with_component(Health(100))?
+            .current_entity();
+        entities.despawn(first)?;
+
+        let second = entities
+            .create_entity()
+            .with_component(Health(40))?
+            .current_entity();
+
+        assert_eq!(
+            second,
+            Entity {
+                index: 0,
+                generation: 1
+            }
+        );
+        assert!(entities.is_alive(second));
+        assert!(!entities.is_alive(first));
+        assert_eq!(
+            entities.despawn(first),
+            Err(EcsError::StaleEntity { entity: first })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn empty_entities_are_not_recycled() {
+        let mut entities = Entities::default();
+        let first = entities.create_entity().current_entity();
+        let second = entities.create_entity().current_entity();
+
+        assert_ne!(first.index, second.index);
+    }
+
+    #[test]
+    fn insert_component_on_earlier_entity() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component::<Health>();
+        entities.register_component::<Speed>();
+        let first = entities
+            .create_entity()
+            .with_component(Health(100))?
+            .current_entity();
         entities.create_entity().with_component(Health(40))?;
-        let entity_map = entities.map[2];
-        assert_eq!(entity_map, 1);
+
+        entities.insert_component(first, Speed(15))?;
+        assert_eq!(entities.map[0], [0, 1].into_iter().collect());
+        assert!(entities.has_component::<Speed>(first));
+
+        entities.insert_component(first, Speed(20))?;
+        let speed = entities.remove_component::<Speed>(first).unwrap();
+        assert_eq!(speed.0, 20);
+        assert_eq!(entities.map[0], BitMask::from_bit(0));
+        assert!(!entities.has_component::<Speed>(first));
+        assert!(entities.remove_component::<Speed>(first).is_none());
 
         Ok(())
     }
+
+    #[test]
+    fn shared_component_is_left_in_place() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component::<Health>();
+        let entity = entities
+            .create_entity()
+            .with_component(Health(100))?
+            .current_entity();
+        // What a `Query::run` result holds on to.
+        let shared = entities.components[&TypeId::of::<Health>()]
+            .get(entity.index)
+            .cloned();
+
+        assert!(entities.remove_component::<Health>(entity).is_none());
+        assert_eq!(
+            entities.check_remove::<Health>(entity),
+            Err(EcsError::AlreadyBorrowed {
+                entity,
+                type_name: Health::TYPE_NAME
+            })
+        );
+        assert!(entities.has_component::<Health>(entity));
+        assert!(entities.removed::<Health>().is_empty());
+        assert_eq!(entities.borrow_at::<Health>(entity.index)?.0, 100);
+
+        drop(shared);
+        assert_eq!(entities.remove_component::<Health>(entity).unwrap().0, 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn component_changes_on_stale_entity_fail() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component::<Health>();
+        let entity = entities
+            .create_entity()
+            .with_component(Health(100))?
+            .current_entity();
+        entities.despawn(entity)?;
+
+        assert_eq!(
+            entities.insert_component(entity, Health(1)),
+            Err(EcsError::StaleEntity { entity })
+        );
+        assert!(!entities.has_component::<Health>(entity));
+        assert!(entities.remove_component::<Health>(entity).is_none());
+        assert_eq!(
+            entities.check_remove::<Health>(entity),
+            Err(EcsError::StaleEntity { entity })
+        );
+
+        Ok(())
+    }
+
+    #[derive(Component)]
+    struct Marker<const A: usize, const B: usize>;
+
+    macro_rules! register_markers {
+        ($entities:ident; [$($a:literal)*]; $bs:tt) => {
+            $(register_markers!(@row $entities; $a; $bs);)*
+        };
+        (@row $entities:ident; $a:literal; [$($b:literal)*]) => {
+            $($entities.register_component::<Marker<$a, $b>>();)*
+        };
+    }
+
+    #[test]
+    fn register_hundreds_of_components() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        register_markers!(
+            entities;
+            [0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19];
+            [0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19]
+        );
+        assert_eq!(entities.bits.len(), 400);
+
+        let entity = entities
+            .create_entity()
+            .with_component(Marker::<0, 0>)?
+            .with_component(Marker::<19, 19>)?
+            .current_entity();
+        entities.create_entity().with_component(Marker::<19, 19>)?;
+
+        assert!(entities.has_component::<Marker<19, 19>>(entity));
+        assert!(!entities.has_component::<Marker<19, 18>>(entity));
+        assert_eq!(entities.map[0], [0, 399].into_iter().collect());
+
+        let mut query = super::query::Query::new(&entities);
+        let query_entities = query
+            .with_component::<Marker<0, 0>>()?
+            .with_component::<Marker<19, 19>>()?
+            .run_query();
+        assert_eq!(query_entities.len(), 1);
+        assert_eq!(query_entities[0].entity(), entity);
+
+        Ok(())
+    }
+
+    #[test]
+    fn register_component_after_creating_entities() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component::<Health>();
+        let entity = entities
+            .create_entity()
+            .with_component(Health(100))?
+            .current_entity();
+
+        entities.register_component::<Speed>();
+        entities.register_component::<Health>();
+        entities.insert_component(entity, Speed(15))?;
+
+        assert!(entities.has_component::<Health>(entity));
+        assert!(entities.has_component::<Speed>(entity));
+        assert_eq!(entities.bits.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sparse_set_components() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component::<Health>();
+        entities.register_component_with::<Speed>(StorageKind::SparseSet);
+        for _ in 0..100 {
+            entities.create_entity().with_component(Health(10))?;
+        }
+        let fast = entities
+            .create_entity()
+            .with_component(Health(100))?
+            .with_component(Speed(50))?
+            .current_entity();
+
+        assert_eq!(
+            entities.storage_kind::<Speed>(),
+            Some(StorageKind::SparseSet)
+        );
+        assert_eq!(entities.storage_kind::<Health>(), Some(StorageKind::Dense));
+
+        let mut query = super::query::Query::new(&entities);
+        let query_entities = query
+            .with_component::<Health>()?
+            .with_component::<Speed>()?
+            .run_query();
+        assert_eq!(query_entities.len(), 1);
+        assert_eq!(query_entities[0].get_component::<Speed>()?.0, 50);
+
+        assert_eq!(entities.remove_component::<Speed>(fast).unwrap().0, 50);
+        assert!(!entities.has_component::<Speed>(fast));
+
+        Ok(())
+    }
+
+    #[test]
+    fn table_components() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component_with::<Health>(StorageKind::Table);
+        entities.register_component_with::<Speed>(StorageKind::Table);
+        let slow = entities.spawn((Health(10),))?;
+        let fast = entities.spawn((Health(100), Speed(50)))?;
+        let other = entities.spawn((Health(20), Speed(5)))?;
+
+        assert_eq!(entities.storage_kind::<Speed>(), Some(StorageKind::Table));
+        let mut query = super::query::Query::new(&entities);
+        let query_entities = query
+            .with_component::<Health>()?
+            .with_component::<Speed>()?
+            .run_query();
+        assert_eq!(query_entities.len(), 2);
+        assert_eq!(query_entities[0].entity(), fast);
+        assert_eq!(query_entities[0].get_component::<Speed>()?.0, 50);
+        query_entities[1].get_component_mut::<Health>()?.0 += 1;
+        assert!(entities.is_changed::<Health>(other));
+
+        // Moves `fast` back to the table of `slow`.
+        assert_eq!(entities.remove_component::<Speed>(fast).unwrap().0, 50);
+        assert!(!entities.has_component::<Speed>(fast));
+        assert_eq!(entities.borrow_at::<Health>(fast.index)?.0, 100);
+        assert_eq!(entities.borrow_at::<Health>(other.index)?.0, 21);
+        entities.despawn(slow)?;
+        assert_eq!(entities.removed::<Health>(), &[slow]);
+        assert_eq!(entities.borrow_at::<Health>(fast.index)?.0, 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn change_ticks_follow_inserts_and_clears() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component::<Health>();
+        entities.register_component::<Speed>();
+        let entity = entities
+            .create_entity()
+            .with_component(Health(100))?
+            .current_entity();
+        assert!(entities.is_added::<Health>(entity));
+        assert!(entities.is_changed::<Health>(entity));
+
+        entities.clear_trackers();
+        assert!(!entities.is_added::<Health>(entity));
+        assert!(!entities.is_changed::<Health>(entity));
+
+        entities.insert_component(entity, Speed(1))?;
+        assert!(entities.is_added::<Speed>(entity));
+        assert!(!entities.is_added::<Health>(entity));
+
+        Ok(())
+    }
+
+    #[test]
+    fn replacing_a_component_is_a_change_not_an_add() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component::<Health>();
+        let entity = entities
+            .create_entity()
+            .with_component(Health(100))?
+            .current_entity();
+        entities.clear_trackers();
+
+        entities.insert_component(entity, Health(50))?;
+        assert!(!entities.is_added::<Health>(entity));
+        assert!(entities.is_changed::<Health>(entity));
+
+        let mut query = super::query::Query::new(&entities);
+        query.added::<Health>()?;
+        assert!(query.run_query().is_empty());
+        let mut query = super::query::Query::new(&entities);
+        query.changed::<Health>()?;
+        assert_eq!(query.run_query().len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn removed_components_are_tracked_until_cleared() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component::<Health>();
+        entities.register_component::<Speed>();
+        let first = entities
+            .create_entity()
+            .with_component(Health(100))?
+            .with_component(Speed(1))?
+            .current_entity();
+        let second = entities
+            .create_entity()
+            .with_component(Health(40))?
+            .current_entity();
+
+        entities.remove_component::<Speed>(first);
+        entities.despawn(second)?;
+        assert_eq!(entities.removed::<Speed>(), &[first]);
+        assert_eq!(entities.removed::<Health>(), &[second]);
+
+        entities.clear_trackers();
+        assert!(entities.removed::<Speed>().is_empty());
+        assert!(entities.removed::<Health>().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn spawn_bundle_registers_on_demand() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component_with::<Speed>(StorageKind::SparseSet);
+        let entity = entities.spawn((Health(100), Speed(15)))?;
+
+        assert!(entities.has_component::<Health>(entity));
+        assert!(entities.has_component::<Speed>(entity));
+        assert_eq!(entities.storage_kind::<Health>(), Some(StorageKind::Dense));
+        assert_eq!(
+            entities.storage_kind::<Speed>(),
+            Some(StorageKind::SparseSet)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn spawn_rejects_duplicate_component_types() {
+        let mut entities = Entities::default();
+
+        assert_eq!(
+            entities.spawn((Health(1), Speed(1), Health(2))),
+            Err(EcsError::DuplicateBundleComponent {
+                type_name: Health::TYPE_NAME
+            })
+        );
+        assert!(entities.map.is_empty());
+        assert!(entities.storage_kind::<Health>().is_none());
+    }
 }
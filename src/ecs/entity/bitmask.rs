@@ -0,0 +1,133 @@
+use std::ops::BitOrAssign;
+
+const BLOCK_BITS: usize = u64::BITS as usize;
+
+/// Growable set of component bits. Each registered component type owns one
+/// bit, and an entity's signature has the bits of every component it holds.
+#[derive(Debug, Clone, Default)]
+pub struct BitMask {
+    blocks: Vec<u64>,
+}
+
+impl BitMask {
+    pub fn from_bit(bit: usize) -> Self {
+        let mut mask = Self::default();
+        mask.insert(bit);
+        mask
+    }
+
+    pub fn insert(&mut self, bit: usize) {
+        let block = bit / BLOCK_BITS;
+        if block >= self.blocks.len() {
+            self.blocks.resize(block + 1, 0);
+        }
+        self.blocks[block] |= 1 << (bit % BLOCK_BITS);
+    }
+
+    pub fn remove(&mut self, bit: usize) {
+        if let Some(block) = self.blocks.get_mut(bit / BLOCK_BITS) {
+            *block &= !(1 << (bit % BLOCK_BITS));
+        }
+    }
+
+    pub fn contains(&self, bit: usize) -> bool {
+        self.blocks
+            .get(bit / BLOCK_BITS)
+            .is_some_and(|block| block & (1 << (bit % BLOCK_BITS)) != 0)
+    }
+
+    pub fn contains_all(&self, other: &BitMask) -> bool {
+        other.blocks.iter().enumerate().all(|(index, other_block)| {
+            let block = self.blocks.get(index).copied().unwrap_or(0);
+            block & other_block == *other_block
+        })
+    }
+
+    pub fn intersects(&self, other: &BitMask) -> bool {
+        self.blocks
+            .iter()
+            .zip(other.blocks.iter())
+            .any(|(block, other_block)| block & other_block != 0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.iter().all(|block| *block == 0)
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+    }
+}
+
+impl PartialEq for BitMask {
+    fn eq(&self, other: &Self) -> bool {
+        self.contains_all(other) && other.contains_all(self)
+    }
+}
+
+impl Eq for BitMask {}
+
+impl BitOrAssign<&BitMask> for BitMask {
+    fn bitor_assign(&mut self, rhs: &BitMask) {
+        if rhs.blocks.len() > self.blocks.len() {
+            self.blocks.resize(rhs.blocks.len(), 0);
+        }
+        self.blocks
+            .iter_mut()
+            .zip(rhs.blocks.iter())
+            .for_each(|(block, rhs_block)| *block |= rhs_block);
+    }
+}
+
+impl FromIterator<usize> for BitMask {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        let mut mask = Self::default();
+        iter.into_iter().for_each(|bit| mask.insert(bit));
+        mask
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn insert_and_remove_bits_past_one_block() {
+        let mut mask = BitMask::default();
+        mask.insert(3);
+        mask.insert(200);
+
+        assert!(mask.contains(3));
+        assert!(mask.contains(200));
+        assert!(!mask.contains(199));
+        assert!(!mask.contains(1000));
+
+        mask.remove(200);
+        assert!(!mask.contains(200));
+        assert_eq!(mask, BitMask::from_bit(3));
+    }
+
+    #[test]
+    fn contains_all_and_intersects() {
+        let entity: BitMask = [0, 70, 130].into_iter().collect();
+        let query: BitMask = [0, 130].into_iter().collect();
+        let other: BitMask = [1, 131].into_iter().collect();
+
+        assert!(entity.contains_all(&query));
+        assert!(!query.contains_all(&entity));
+        assert!(entity.intersects(&query));
+        assert!(!entity.intersects(&other));
+        assert!(entity.contains_all(&BitMask::default()));
+    }
+
+    #[test]
+    fn or_assign_grows_mask() {
+        let mut mask = BitMask::from_bit(1);
+        mask |= &BitMask::from_bit(100);
+
+        assert_eq!(mask, [1, 100].into_iter().collect());
+        assert!(!mask.is_empty());
+        mask.clear();
+        assert!(mask.is_empty());
+    }
+}
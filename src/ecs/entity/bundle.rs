@@ -0,0 +1,91 @@
+use std::any::TypeId;
+
+use super::{super::Component, EcsError, Entities, Entity};
+
+/// A group of components spawned together, e.g.
+/// `(Position { x: 1, y: 1 }, Renderable { display: '@' })`.
+pub trait Bundle: 'static {
+    fn type_ids() -> Vec<TypeId>;
+
+    fn type_names() -> Vec<&'static str>;
+
+    /// Registers every component type that isn't registered yet, with its
+    /// `Component::STORAGE`.
+    fn register(entities: &mut Entities);
+
+    fn insert(self, entities: &mut Entities, entity: Entity) -> Result<(), EcsError>;
+}
+
+/// No components, e.g. for `World::spawn_prefab` without extras.
+impl Bundle for () {
+    fn type_ids() -> Vec<TypeId> {
+        vec![]
+    }
+
+    fn type_names() -> Vec<&'static str> {
+        vec![]
+    }
+
+    fn register(_entities: &mut Entities) {}
+
+    fn insert(self, _entities: &mut Entities, _entity: Entity) -> Result<(), EcsError> {
+        Ok(())
+    }
+}
+
+macro_rules! impl_bundle_for_tuple {
+    ($(($name:ident, $index:tt)),+) => {
+        impl<$($name: Component),+> Bundle for ($($name,)+) {
+            fn type_ids() -> Vec<TypeId> {
+                vec![$(TypeId::of::<$name>()),+]
+            }
+
+            fn type_names() -> Vec<&'static str> {
+                vec![$($name::TYPE_NAME),+]
+            }
+
+            fn register(entities: &mut Entities) {
+                $(entities.register_component_with::<$name>($name::STORAGE);)+
+            }
+
+            fn insert(self, entities: &mut Entities, entity: Entity) -> Result<(), EcsError> {
+                $(entities.insert_component(entity, self.$index)?;)+
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_bundle_for_tuple!((A, 0));
+impl_bundle_for_tuple!((A, 0), (B, 1));
+impl_bundle_for_tuple!((A, 0), (B, 1), (C, 2));
+impl_bundle_for_tuple!((A, 0), (B, 1), (C, 2), (D, 3));
+impl_bundle_for_tuple!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4));
+impl_bundle_for_tuple!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4), (F, 5));
+impl_bundle_for_tuple!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4), (F, 5), (G, 6));
+impl_bundle_for_tuple!(
+    (A, 0),
+    (B, 1),
+    (C, 2),
+    (D, 3),
+    (E, 4),
+    (F, 5),
+    (G, 6),
+    (H, 7)
+);
+
+/// Checks that no component type appears twice, which would silently drop
+/// one of the values.
+pub fn check<B: Bundle>() -> Result<(), EcsError> {
+    let type_ids = B::type_ids();
+    let duplicate = type_ids
+        .iter()
+        .enumerate()
+        .position(|(index, type_id)| type_ids[..index].contains(type_id));
+    match duplicate {
+        Some(index) => Err(EcsError::DuplicateBundleComponent {
+            type_name: B::type_names()[index],
+        }),
+        None => Ok(()),
+    }
+}
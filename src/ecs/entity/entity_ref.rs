@@ -0,0 +1,186 @@
+use std::cell::{Ref, RefMut};
+
+use super::{
+    super::{Component, World},
+    EcsError, Entities, Entity,
+};
+
+/// Read access to one entity's components, from `World::entity`.
+#[derive(Debug, Clone, Copy)]
+pub struct EntityRef<'a> {
+    entity: Entity,
+    entities: &'a Entities,
+}
+
+impl<'a> EntityRef<'a> {
+    pub fn new(entity: Entity, entities: &'a Entities) -> Result<Self, EcsError> {
+        if !entities.is_alive(entity) {
+            return Err(EcsError::StaleEntity { entity });
+        }
+        Ok(Self { entity, entities })
+    }
+
+    pub fn id(&self) -> Entity {
+        self.entity
+    }
+
+    pub fn get<T: Component>(&self) -> Result<Ref<'a, T>, EcsError> {
+        self.entities.borrow_at::<T>(self.entity.index)
+    }
+
+    /// Mutable borrow through a shared view; it goes through the component's
+    /// `RefCell` like `QueryEntity::get_component_mut`.
+    pub fn get_mut<T: Component>(&self) -> Result<RefMut<'a, T>, EcsError> {
+        self.entities.borrow_mut_at::<T>(self.entity.index)
+    }
+
+    pub fn contains<T: Component>(&self) -> bool {
+        self.entities.has_component::<T>(self.entity)
+    }
+
+    /// Type names of this entity's components, in registration order.
+    pub fn components(&self) -> Vec<&'static str> {
+        self.entities.component_names(self.entity)
+    }
+}
+
+/// Read and write access to one entity, from `World::entity_mut`. Unlike
+/// `EntityRef` it can also add and remove components, which goes through
+/// `World` so hooks run right away. A hook may despawn the entity, after
+/// which every call fails with `StaleEntity`.
+#[derive(Debug)]
+pub struct EntityMut<'a> {
+    entity: Entity,
+    world: &'a mut World,
+}
+
+impl<'a> EntityMut<'a> {
+    pub fn new(entity: Entity, world: &'a mut World) -> Result<Self, EcsError> {
+        if !world.is_alive(entity) {
+            return Err(EcsError::StaleEntity { entity });
+        }
+        Ok(Self { entity, world })
+    }
+
+    pub fn id(&self) -> Entity {
+        self.entity
+    }
+
+    pub fn get<T: Component>(&self) -> Result<Ref<'_, T>, EcsError> {
+        self.world.entity(self.entity)?.get::<T>()
+    }
+
+    pub fn get_mut<T: Component>(&mut self) -> Result<RefMut<'_, T>, EcsError> {
+        self.world.entity(self.entity)?.get_mut::<T>()
+    }
+
+    pub fn contains<T: Component>(&self) -> bool {
+        self.world.has_component::<T>(self.entity)
+    }
+
+    pub fn components(&self) -> Vec<&'static str> {
+        self.world
+            .entity(self.entity)
+            .map(|entity| entity.components())
+            .unwrap_or_default()
+    }
+
+    pub fn insert(&mut self, data: impl Component) -> Result<&mut Self, EcsError> {
+        self.world.insert_component(self.entity, data)?;
+        Ok(self)
+    }
+
+    pub fn remove<T: Component>(&mut self) -> Option<T> {
+        self.world.remove_component::<T>(self.entity)
+    }
+}
+
+/// Returned by `World::create_entity`. Like `EntityMut::insert`, each
+/// component goes through `World::insert_component`.
+#[derive(Debug)]
+pub struct EntityBuilder<'a> {
+    entity: Entity,
+    world: &'a mut World,
+}
+
+impl<'a> EntityBuilder<'a> {
+    pub(crate) fn new(entity: Entity, world: &'a mut World) -> Self {
+        Self { entity, world }
+    }
+
+    pub fn with_component(&mut self, data: impl Component) -> Result<&mut Self, EcsError> {
+        self.world.insert_component(self.entity, data)?;
+        Ok(self)
+    }
+
+    pub fn current_entity(&self) -> Entity {
+        self.entity
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ecs::Component;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Component)]
+    struct Health(pub u32);
+    #[derive(Component)]
+    struct Target(pub Entity);
+
+    #[test]
+    fn follow_a_target_by_handle() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        let player = entities.spawn((Health(100),))?;
+        let monster = entities.spawn((Health(10), Target(player)))?;
+
+        let target = EntityRef::new(monster, &entities)?.get::<Target>()?.0;
+        EntityRef::new(target, &entities)?.get_mut::<Health>()?.0 -= 5;
+
+        let player = EntityRef::new(player, &entities)?;
+        assert_eq!(*player.get::<Health>()?, Health(95));
+        assert!(!player.contains::<Target>());
+        assert_eq!(
+            player.get::<Target>().err(),
+            Some(EcsError::ComponentMissing {
+                entity: player.id(),
+                type_name: Target::TYPE_NAME
+            })
+        );
+        let health = player.get_mut::<Health>()?;
+        assert!(matches!(
+            player.get::<Health>(),
+            Err(EcsError::AlreadyBorrowed { .. })
+        ));
+        drop(health);
+        assert!(entities.is_changed::<Health>(player.id()));
+
+        let monster = EntityRef::new(monster, &entities)?;
+        assert_eq!(monster.components().len(), 2);
+        assert_eq!(monster.components()[1], "Target");
+
+        Ok(())
+    }
+
+    #[test]
+    fn entity_mut_adds_and_removes() -> Result<(), EcsError> {
+        let mut world = World::new();
+        world.register_component::<Health>();
+        let entity = world.create_entity().current_entity();
+
+        let mut entity_mut = EntityMut::new(entity, &mut world)?;
+        entity_mut.insert(Health(3))?;
+        entity_mut.get_mut::<Health>()?.0 += 1;
+        assert_eq!(entity_mut.remove::<Health>(), Some(Health(4)));
+        assert!(entity_mut.components().is_empty());
+
+        world.despawn(entity)?;
+        assert_eq!(
+            world.entity(entity).err(),
+            Some(EcsError::StaleEntity { entity })
+        );
+
+        Ok(())
+    }
+}
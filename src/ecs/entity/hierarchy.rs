@@ -0,0 +1,124 @@
+use super::{EcsError, Entities, Entity};
+
+/// Parent/child relations, e.g. an item inside a backpack inside the player.
+/// Despawning an entity despawns its descendants too.
+impl Entities {
+    /// Makes `child` a child of `parent`, detaching it from its old parent.
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) -> Result<(), EcsError> {
+        for entity in [child, parent] {
+            if !self.is_alive(entity) {
+                return Err(EcsError::StaleEntity { entity });
+            }
+        }
+        if child == parent || self.descendants(child).contains(&parent) {
+            return Err(EcsError::HierarchyCycle { child, parent });
+        }
+        self.remove_parent(child);
+        self.parents[child.index] = Some(parent);
+        self.children[parent.index].push(child);
+        Ok(())
+    }
+
+    /// Detaches `child` from its parent. Returns the old parent.
+    pub fn remove_parent(&mut self, child: Entity) -> Option<Entity> {
+        if !self.is_alive(child) {
+            return None;
+        }
+        let parent = self.parents[child.index].take()?;
+        self.children[parent.index].retain(|sibling| *sibling != child);
+        Some(parent)
+    }
+
+    pub fn parent(&self, child: Entity) -> Option<Entity> {
+        if !self.is_alive(child) {
+            return None;
+        }
+        self.parents[child.index]
+    }
+
+    /// Direct children of `parent`, in the order they were added.
+    pub fn children(&self, parent: Entity) -> &[Entity] {
+        if !self.is_alive(parent) {
+            return &[];
+        }
+        &self.children[parent.index]
+    }
+
+    /// Children, grandchildren and so on, parents before their children.
+    pub fn descendants(&self, parent: Entity) -> Vec<Entity> {
+        let mut descendants = self.children(parent).to_vec();
+        let mut next = 0;
+        while let Some(entity) = descendants.get(next).copied() {
+            descendants.extend_from_slice(self.children(entity));
+            next += 1;
+        }
+        descendants
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ecs::Component;
+
+    use super::*;
+
+    #[derive(Component)]
+    struct Item;
+
+    #[test]
+    fn set_parent_moves_children() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        let player = entities.spawn((Item,))?;
+        let backpack = entities.spawn((Item,))?;
+        let chest = entities.spawn((Item,))?;
+        let potion = entities.spawn((Item,))?;
+
+        entities.set_parent(backpack, player)?;
+        entities.set_parent(potion, chest)?;
+        entities.set_parent(potion, backpack)?;
+
+        assert_eq!(entities.parent(potion), Some(backpack));
+        assert_eq!(entities.children(backpack), &[potion]);
+        assert!(entities.children(chest).is_empty());
+        assert_eq!(entities.descendants(player), vec![backpack, potion]);
+        assert_eq!(
+            entities.set_parent(player, potion),
+            Err(EcsError::HierarchyCycle {
+                child: player,
+                parent: potion
+            })
+        );
+
+        assert_eq!(entities.remove_parent(potion), Some(backpack));
+        assert!(entities.children(backpack).is_empty());
+        assert_eq!(entities.parent(potion), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn despawn_cascades_to_descendants() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        let player = entities.spawn((Item,))?;
+        let backpack = entities.spawn((Item,))?;
+        let potion = entities.spawn((Item,))?;
+        let sword = entities.spawn((Item,))?;
+        entities.set_parent(backpack, player)?;
+        entities.set_parent(potion, backpack)?;
+        entities.set_parent(sword, player)?;
+
+        entities.despawn(backpack)?;
+        assert!(!entities.is_alive(backpack));
+        assert!(!entities.is_alive(potion));
+        assert_eq!(entities.children(player), &[sword]);
+
+        // Recycled slots start without relations.
+        let recycled = entities.spawn((Item,))?;
+        assert_eq!(recycled.index, backpack.index);
+        assert_eq!(entities.parent(recycled), None);
+        assert!(entities.children(recycled).is_empty());
+        assert!(entities.children(backpack).is_empty());
+
+        Ok(())
+    }
+}
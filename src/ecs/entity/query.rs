@@ -1,100 +1,306 @@
 use std::{
-    any::{Any, TypeId},
+    any::TypeId,
     cell::{Ref, RefMut},
+    cmp::Ordering,
+    fmt,
 };
 
-use super::{Component, Entities};
+use super::{
+    super::Component,
+    bitmask::BitMask,
+    storage::StorageKind,
+    table::{Table, TableColumn},
+    EcsError, Entities, Entity,
+};
 
 pub type QueryIndexes = Vec<usize>;
-pub type QueryComponents = Vec<Vec<Component>>;
+pub type QueryComponents = Vec<Vec<super::Component>>;
+pub type QueryOptionalComponents = Vec<Vec<Option<super::Component>>>;
 
 #[derive(Debug)]
 pub struct QueryEntity<'a> {
     id: usize,
     entities: &'a Entities,
+    /// The entity's table and row, so its table components are read straight
+    /// from their columns.
+    row: Option<(&'a Table, usize)>,
 }
 
 impl<'a> QueryEntity<'a> {
     pub fn new(id: usize, entities: &'a Entities) -> Self {
-        QueryEntity { id, entities }
+        let tables = entities.tables();
+        let row = tables.row(id).map(|row| (tables.table(row.table), row.row));
+        QueryEntity { id, entities, row }
     }
 
-    pub fn get_component<T: Any>(&self) -> Result<Ref<T>, &'static str> {
-        let type_id = TypeId::of::<T>();
-        let components = self
-            .entities
-            .components
-            .get(&type_id)
-            .ok_or("Component not registered")?;
-        let borrow_component = components[self.id]
-            .as_ref()
-            .ok_or("Component not found")?
-            .borrow();
-
-        Ok(Ref::map(borrow_component, |any| {
-            any.downcast_ref::<T>().unwrap()
-        }))
-    }
-
-    pub fn get_component_mut<T: Any>(&self) -> Result<RefMut<T>, &'static str> {
-        let type_id = TypeId::of::<T>();
-        let components = self
-            .entities
-            .components
-            .get(&type_id)
-            .ok_or("Component not registered")?;
-        let borrow_component = components[self.id]
-            .as_ref()
-            .ok_or("Component not found")?
-            .borrow_mut();
-
-        Ok(RefMut::map(borrow_component, |any| {
-            any.downcast_mut::<T>().unwrap()
-        }))
+    pub fn entity(&self) -> Entity {
+        self.entities.entity_at(self.id)
+    }
+
+    pub fn get_component<T: Component>(&self) -> Result<Ref<'_, T>, EcsError> {
+        match self.column::<T>() {
+            Some((column, row)) => column.borrow(row).ok_or(self.already_borrowed::<T>()),
+            None => self.entities.borrow_at::<T>(self.id),
+        }
+    }
+
+    pub fn get_component_mut<T: Component>(&self) -> Result<RefMut<'_, T>, EcsError> {
+        match self.column::<T>() {
+            Some((column, row)) => column
+                .borrow_mut(row, self.entities.change_tick())
+                .ok_or(self.already_borrowed::<T>()),
+            None => self.entities.borrow_mut_at::<T>(self.id),
+        }
+    }
+
+    fn column<T: Component>(&self) -> Option<(&'a TableColumn<T>, usize)> {
+        let (table, row) = self.row?;
+        Some((table.column::<T>()?, row))
+    }
+
+    fn already_borrowed<T: Component>(&self) -> EcsError {
+        EcsError::AlreadyBorrowed {
+            entity: self.entity(),
+            type_name: T::TYPE_NAME,
+        }
+    }
+}
+
+type Compare = dyn Fn(&Entities, usize, usize) -> Ordering;
+
+/// Compares two slot indexes, from `Query::order_by` or `order_by_key`.
+struct OrderBy(Box<Compare>);
+
+impl fmt::Debug for OrderBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OrderBy")
     }
 }
 
 #[derive(Debug)]
 pub struct Query<'a> {
-    map: u32,
-    entities: &'a Entities,
+    map: BitMask,
+    without_map: BitMask,
+    any_map: BitMask,
+    any_of_used: bool,
+    pub(super) entities: &'a Entities,
     type_ids: Vec<TypeId>,
+    /// The `type_ids` stored in tables; only their tables are searched.
+    table_type_ids: Vec<TypeId>,
+    optional_type_ids: Vec<TypeId>,
+    added_type_ids: Vec<TypeId>,
+    changed_type_ids: Vec<TypeId>,
+    parent: Option<Entity>,
+    orderings: Vec<OrderBy>,
 }
 
 impl<'a> Query<'a> {
     pub fn new(entities: &'a Entities) -> Self {
         Self {
             entities,
-            map: 0,
+            map: BitMask::default(),
+            without_map: BitMask::default(),
+            any_map: BitMask::default(),
+            any_of_used: false,
             type_ids: vec![],
+            table_type_ids: vec![],
+            optional_type_ids: vec![],
+            added_type_ids: vec![],
+            changed_type_ids: vec![],
+            parent: None,
+            orderings: vec![],
         }
     }
 
-    pub fn with_component<T: Any>(&mut self) -> Result<&mut Self, &'static str> {
+    pub fn with_component<T: Component>(&mut self) -> Result<&mut Self, EcsError> {
         let type_id = TypeId::of::<T>();
         if let Some(bit_mask) = self.entities.get_bitmask(&type_id) {
-            self.map |= bit_mask;
+            self.map |= &bit_mask;
             self.type_ids.push(type_id);
+            if self.entities.storage_kind::<T>() == Some(StorageKind::Table) {
+                self.table_type_ids.push(type_id);
+            }
         } else {
-            return Err("Component not registered");
+            return Err(EcsError::ComponentNotRegistered {
+                type_name: T::TYPE_NAME,
+            });
         }
         Ok(self)
     }
 
-    pub fn run(&self) -> (QueryIndexes, QueryComponents) {
-        let indexes: Vec<usize> = self
-            .entities
-            .map
-            .iter()
-            .enumerate()
-            .filter_map(|(index, entity_map)| {
-                if entity_map & self.map == self.map {
-                    Some(index)
-                } else {
-                    None
-                }
+    /// Skips entities that have `T`. A component that was never registered
+    /// can't be on any entity, so it filters nothing out.
+    pub fn without<T: Component>(&mut self) -> &mut Self {
+        if let Some(bit_mask) = self.entities.get_bitmask(&TypeId::of::<T>()) {
+            self.without_map |= &bit_mask;
+        }
+        self
+    }
+
+    /// Adds `T` to the query's any-of group: entities must have at least one
+    /// of the components added through `any_of`.
+    pub fn any_of<T: Component>(&mut self) -> &mut Self {
+        if let Some(bit_mask) = self.entities.get_bitmask(&TypeId::of::<T>()) {
+            self.any_map |= &bit_mask;
+        }
+        self.any_of_used = true;
+        self
+    }
+
+    /// Fetches `T` for every matching entity without requiring it. `run`
+    /// returns optional components in their own columns, in the order they
+    /// were added.
+    pub fn optional<T: Component>(&mut self) -> &mut Self {
+        self.optional_type_ids.push(TypeId::of::<T>());
+        self
+    }
+
+    /// Like `with_component`, but only matches entities that got their `T`
+    /// since the last `clear_trackers`. Replacing a `T` doesn't count.
+    pub fn added<T: Component>(&mut self) -> Result<&mut Self, EcsError> {
+        self.with_component::<T>()?;
+        self.added_type_ids.push(TypeId::of::<T>());
+        Ok(self)
+    }
+
+    /// Like `with_component`, but only matches entities whose `T` was
+    /// inserted or mutably borrowed since the last `clear_trackers`.
+    pub fn changed<T: Component>(&mut self) -> Result<&mut Self, EcsError> {
+        self.with_component::<T>()?;
+        self.changed_type_ids.push(TypeId::of::<T>());
+        Ok(self)
+    }
+
+    /// Only matches direct children of `parent`.
+    pub fn children_of(&mut self, parent: Entity) -> &mut Self {
+        self.parent = Some(parent);
+        self
+    }
+
+    /// Sorts matches by their `T`, smallest first. Entities without `T`, or
+    /// whose `T` is mutably borrowed, come last. Calling it again adds a
+    /// tiebreaker; entities that are still tied stay oldest first.
+    pub fn order_by<T: Component + Ord>(&mut self) -> &mut Self {
+        self.order_with::<T>(T::cmp)
+    }
+
+    /// Like `order_by`, but sorts by `key` of each entity's `T`, e.g.
+    /// `|order: &RenderOrder| Reverse(order.0)`.
+    pub fn order_by_key<T: Component, K: Ord>(
+        &mut self,
+        key: impl Fn(&T) -> K + 'static,
+    ) -> &mut Self {
+        self.order_with::<T>(move |a, b| key(a).cmp(&key(b)))
+    }
+
+    fn order_with<T: Component>(
+        &mut self,
+        compare: impl Fn(&T, &T) -> Ordering + 'static,
+    ) -> &mut Self {
+        self.orderings.push(OrderBy(Box::new(move |entities, a, b| {
+            match (entities.borrow_at::<T>(a), entities.borrow_at::<T>(b)) {
+                (Ok(a), Ok(b)) => compare(&a, &b),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => Ordering::Equal,
+            }
+        })));
+        self
+    }
+
+    pub fn matches(&self, index: usize) -> bool {
+        let entity_map = &self.entities.map[index];
+        let change_tick = self.entities.change_tick();
+        self.entities.alive[index]
+            && entity_map.contains_all(&self.map)
+            && !entity_map.intersects(&self.without_map)
+            && (!self.any_of_used || entity_map.intersects(&self.any_map))
+            && (self.parent.is_none() || self.entities.parents[index] == self.parent)
+            && self.added_type_ids.iter().all(|type_id| {
+                self.entities
+                    .get_ticks(type_id, index)
+                    .is_some_and(|ticks| ticks.added == change_tick)
             })
-            .collect();
+            && self.changed_type_ids.iter().all(|type_id| {
+                self.entities
+                    .get_ticks(type_id, index)
+                    .is_some_and(|ticks| ticks.changed.get() == change_tick)
+            })
+    }
+
+    /// Whether every row of a matching table matches, i.e. the query asks
+    /// for nothing but table components.
+    fn only_tables(&self) -> bool {
+        self.type_ids.len() == self.table_type_ids.len()
+            && self.without_map.is_empty()
+            && !self.any_of_used
+            && self.parent.is_none()
+            && self.added_type_ids.is_empty()
+            && self.changed_type_ids.is_empty()
+    }
+
+    /// Matching slot indexes, oldest entity first unless `order_by` or
+    /// `order_by_key` say otherwise.
+    pub fn indexes(&self) -> QueryIndexes {
+        let mut indexes: QueryIndexes = if self.table_type_ids.is_empty() {
+            (0..self.entities.map.len())
+                .filter(|index| self.matches(*index))
+                .collect()
+        } else {
+            let rows = self
+                .entities
+                .tables()
+                .matching(&self.table_type_ids)
+                .flat_map(|table| table.indexes())
+                .copied();
+            if self.only_tables() {
+                rows.collect()
+            } else {
+                rows.filter(|index| self.matches(*index)).collect()
+            }
+        };
+        // Slots are only out of spawn order once one has been recycled or
+        // a table row has moved, so checking is usually all it takes.
+        let spawn_sequence = |index: &usize| self.entities.spawn_sequence[*index];
+        if !indexes.is_sorted_by_key(spawn_sequence) {
+            indexes.sort_by_key(spawn_sequence);
+        }
+        if !self.orderings.is_empty() {
+            // Stable, so ties stay oldest first.
+            indexes.sort_by(|a, b| {
+                self.orderings
+                    .iter()
+                    .map(|ordering| (ordering.0)(self.entities, *a, *b))
+                    .find(|order| order.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            });
+        }
+        indexes
+    }
+
+    /// Components of every match, in the order of `indexes`. Fails with
+    /// `UnsupportedStorage` if a fetched component uses `StorageKind::Table`:
+    /// those live in their table and can't be shared, so fetch them with
+    /// `run_query` or a `TypedQuery` instead.
+    pub fn run(
+        &self,
+    ) -> Result<(QueryIndexes, QueryComponents, QueryOptionalComponents), EcsError> {
+        let in_table = self
+            .type_ids
+            .iter()
+            .chain(&self.optional_type_ids)
+            .find(|type_id| {
+                self.entities
+                    .components
+                    .get(type_id)
+                    .is_some_and(|components| components.kind() == StorageKind::Table)
+            });
+        if let Some(type_id) = in_table {
+            return Err(EcsError::UnsupportedStorage {
+                type_name: self.entities.names[type_id],
+            });
+        }
+        let indexes = self.indexes();
 
         let mut result = vec![];
 
@@ -102,91 +308,118 @@ impl<'a> Query<'a> {
             let entity_components = self.entities.components.get(type_id).unwrap();
             let mut components_to_keep = vec![];
             for index in &indexes {
-                // components_to_keep.push(entity_components[*index].as_ref().unwrap().clone());
-                components_to_keep.push(entity_components[*index].as_ref().unwrap().clone());
+                components_to_keep.push(entity_components.get(*index).unwrap().clone());
             }
             result.push(components_to_keep);
         }
 
-        (indexes, result)
-    }
-
-    pub fn run_query(&self) -> Vec<QueryEntity> {
-        self.entities
-            .map
+        let optional_result = self
+            .optional_type_ids
             .iter()
-            .enumerate()
-            .filter_map(|(index, entity_map)| {
-                if entity_map & self.map == self.map {
-                    Some(QueryEntity::new(index, self.entities))
-                } else {
-                    None
-                }
+            .map(|type_id| {
+                indexes
+                    .iter()
+                    .map(|index| {
+                        self.entities
+                            .components
+                            .get(type_id)
+                            .and_then(|components| components.get(*index).cloned())
+                    })
+                    .collect()
             })
+            .collect();
+
+        Ok((indexes, result, optional_result))
+    }
+
+    /// Every match, in the order of `indexes`.
+    pub fn run_query(&self) -> Vec<QueryEntity<'a>> {
+        self.indexes()
+            .into_iter()
+            .map(|index| QueryEntity::new(index, self.entities))
             .collect()
     }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::ecs::Component;
+
     use super::*;
 
+    #[derive(Debug, PartialEq, Component)]
+    struct Health(pub u32);
+    #[derive(Debug, PartialEq, Component)]
+    struct Speed(pub f32);
+    #[derive(Component)]
+    struct Flag;
+    #[derive(Component)]
+    struct Unused;
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Component)]
+    struct Rank(pub u32);
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Component)]
+    struct Letter(pub char);
+
     #[test]
-    fn query_mask_updating_with_component() -> Result<(), &'static str> {
+    fn query_mask_updating_with_component() -> Result<(), EcsError> {
         let mut entities = Entities::default();
-        entities.register_component::<u32>();
-        entities.register_component::<f32>();
+        entities.register_component::<Health>();
+        entities.register_component::<Speed>();
         let mut query = Query::new(&entities);
-        query.with_component::<u32>()?.with_component::<f32>()?;
+        query
+            .with_component::<Health>()?
+            .with_component::<Speed>()?;
 
-        assert_eq!(query.map, 3);
-        assert_eq!(TypeId::of::<u32>(), query.type_ids[0]);
-        assert_eq!(TypeId::of::<f32>(), query.type_ids[1]);
+        assert_eq!(query.map, [0, 1].into_iter().collect());
+        assert_eq!(TypeId::of::<Health>(), query.type_ids[0]);
+        assert_eq!(TypeId::of::<Speed>(), query.type_ids[1]);
 
         Ok(())
     }
 
     #[test]
-    fn run_query() -> Result<(), &'static str> {
+    fn run_query() -> Result<(), EcsError> {
         let mut entities = Entities::default();
-        entities.register_component::<u32>();
-        entities.register_component::<f32>();
+        entities.register_component::<Health>();
+        entities.register_component::<Speed>();
         entities
             .create_entity()
-            .with_component(10_u32)?
-            .with_component(20.0_f32)?;
-        entities.create_entity().with_component(5_u32)?;
-        entities.create_entity().with_component(50.0_f32)?;
+            .with_component(Health(10))?
+            .with_component(Speed(20.0))?;
+        entities.create_entity().with_component(Health(5))?;
+        entities.create_entity().with_component(Speed(50.0))?;
         entities
             .create_entity()
-            .with_component(15_u32)?
-            .with_component(25.0_f32)?;
+            .with_component(Health(15))?
+            .with_component(Speed(25.0))?;
         let mut query = Query::new(&entities);
-        query.with_component::<u32>()?.with_component::<f32>()?;
+        query
+            .with_component::<Health>()?
+            .with_component::<Speed>()?;
 
-        let query_result = query.run();
-        let u32s = &query_result.1[0];
-        let f32s = &query_result.1[1];
+        let query_result = query.run()?;
+        let healths = &query_result.1[0];
+        let speeds = &query_result.1[1];
         let indexes = &query_result.0;
 
-        assert!(u32s.len() == f32s.len() && u32s.len() == indexes.len());
-        assert_eq!(u32s.len(), 2);
+        assert!(healths.len() == speeds.len() && healths.len() == indexes.len());
+        assert_eq!(healths.len(), 2);
 
-        let borrowed_first_u32 = u32s[0].borrow();
-        let first_u32 = borrowed_first_u32.downcast_ref::<u32>().unwrap();
-        assert_eq!(*first_u32, 10);
+        let borrowed_first_health = healths[0].borrow();
+        let first_health = borrowed_first_health.downcast_ref::<Health>().unwrap();
+        assert_eq!(*first_health, Health(10));
 
-        let borrowed_first_f32 = f32s[0].borrow();
-        let first_f32 = borrowed_first_f32.downcast_ref::<f32>().unwrap();
-        assert_eq!(*first_f32, 20.0);
+        let borrowed_first_speed = speeds[0].borrow();
+        let first_speed = borrowed_first_speed.downcast_ref::<Speed>().unwrap();
+        assert_eq!(*first_speed, Speed(20.0));
 
-        let borrowed_second_u32 = u32s[1].borrow();
-        let second_u32 = borrowed_second_u32.downcast_ref::<u32>().unwrap();
-        assert_eq!(*second_u32, 15);
+        let borrowed_second_health = healths[1].borrow();
+        let second_health = borrowed_second_health.downcast_ref::<Health>().unwrap();
+        assert_eq!(*second_health, Health(15));
 
-        let borrowed_second_f32 = f32s[1].borrow();
-        let second_f32 = borrowed_second_f32.downcast_ref::<f32>().unwrap();
-        assert_eq!(*second_f32, 25.0);
+        let borrowed_second_speed = speeds[1].borrow();
+        let second_speed = borrowed_second_speed.downcast_ref::<Speed>().unwrap();
+        assert_eq!(*second_speed, Speed(25.0));
 
         assert_eq!(indexes[0], 0);
         assert_eq!(indexes[1], 3);
@@ -195,59 +428,219 @@ mod test {
     }
 
     #[test]
-    fn run_entity_query_ref() -> Result<(), &'static str> {
+    fn run_entity_query_ref() -> Result<(), EcsError> {
         let mut entities = Entities::default();
-        entities.register_component::<u32>();
-        entities.register_component::<f32>();
+        entities.register_component::<Health>();
+        entities.register_component::<Speed>();
 
-        entities.create_entity().with_component(5_u32)?;
-        entities.create_entity().with_component(50.0_f32)?;
+        entities.create_entity().with_component(Health(5))?;
+        entities.create_entity().with_component(Speed(50.0))?;
 
         let mut query = Query::new(&entities);
 
-        let query_entities = query.with_component::<u32>()?.run_query();
+        let query_entities = query.with_component::<Health>()?.run_query();
 
         assert_eq!(query_entities.len(), 1);
 
         for entity in query_entities {
             assert_eq!(entity.id, 0);
-            let health = entity.get_component::<u32>()?;
-            assert_eq!(*health, 5);
+            let health = entity.get_component::<Health>()?;
+            assert_eq!(*health, Health(5));
         }
 
         Ok(())
     }
 
     #[test]
-    fn run_entity_query_mut() -> Result<(), &'static str> {
+    fn run_entity_query_mut() -> Result<(), EcsError> {
         let mut entities = Entities::default();
-        entities.register_component::<u32>();
-        entities.register_component::<f32>();
+        entities.register_component::<Health>();
+        entities.register_component::<Speed>();
 
-        entities.create_entity().with_component(5_u32)?;
-        entities.create_entity().with_component(50.0_f32)?;
+        entities.create_entity().with_component(Health(5))?;
+        entities.create_entity().with_component(Speed(50.0))?;
 
         let mut query = Query::new(&entities);
 
-        let query_entities = query.with_component::<u32>()?.run_query();
+        let query_entities = query.with_component::<Health>()?.run_query();
 
         assert_eq!(query_entities.len(), 1);
 
         for entity in query_entities {
             assert_eq!(entity.id, 0);
-            let mut health = entity.get_component_mut::<u32>()?;
-            assert_eq!(*health, 5);
-            *health *= 10;
+            let mut health = entity.get_component_mut::<Health>()?;
+            assert_eq!(*health, Health(5));
+            health.0 *= 10;
         }
 
-        let query_entities = query.with_component::<u32>()?.run_query();
+        let query_entities = query.with_component::<Health>()?.run_query();
 
         for entity in query_entities {
             assert_eq!(entity.id, 0);
-            let health = entity.get_component::<u32>()?;
-            assert_eq!(*health, 50);
+            let health = entity.get_component::<Health>()?;
+            assert_eq!(*health, Health(50));
         }
 
         Ok(())
     }
+
+    #[test]
+    fn run_query_without_component() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component::<Health>();
+        entities.register_component::<Speed>();
+        entities
+            .create_entity()
+            .with_component(Health(10))?
+            .with_component(Speed(20.0))?;
+        entities.create_entity().with_component(Health(5))?;
+
+        let mut query = Query::new(&entities);
+        let query_entities = query
+            .with_component::<Health>()?
+            .without::<Speed>()
+            .without::<Unused>()
+            .run_query();
+
+        assert_eq!(query_entities.len(), 1);
+        assert_eq!(*query_entities[0].get_component::<Health>()?, Health(5));
+
+        Ok(())
+    }
+
+    #[test]
+    fn run_query_any_of_components() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component::<Health>();
+        entities.register_component::<Speed>();
+        entities.register_component::<Flag>();
+        entities.create_entity().with_component(Health(10))?;
+        entities.create_entity().with_component(Flag)?;
+        entities.create_entity().with_component(Speed(20.0))?;
+
+        let mut query = Query::new(&entities);
+        let indexes = query.any_of::<Health>().any_of::<Speed>().indexes();
+        assert_eq!(indexes, vec![0, 2]);
+
+        let mut query = Query::new(&entities);
+        assert!(query.any_of::<Unused>().indexes().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn run_with_optional_component() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component::<Health>();
+        entities.register_component::<Speed>();
+        entities
+            .create_entity()
+            .with_component(Health(10))?
+            .with_component(Speed(20.0))?;
+        entities.create_entity().with_component(Health(5))?;
+
+        let mut query = Query::new(&entities);
+        let (indexes, components, optional_components) = query
+            .with_component::<Health>()?
+            .optional::<Speed>()
+            .run()?;
+
+        assert_eq!(indexes, vec![0, 1]);
+        assert_eq!(components[0].len(), 2);
+        let speeds = &optional_components[0];
+        let first_speed = speeds[0].as_ref().unwrap().borrow();
+        assert_eq!(*first_speed.downcast_ref::<Speed>().unwrap(), Speed(20.0));
+        assert!(speeds[1].is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn run_query_added_and_changed() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component::<Health>();
+        entities.create_entity().with_component(Health(5))?;
+        entities.create_entity().with_component(Health(6))?;
+        entities.clear_trackers();
+        let third = entities
+            .create_entity()
+            .with_component(Health(7))?
+            .current_entity();
+
+        let mut query = Query::new(&entities);
+        let added = query.added::<Health>()?.run_query();
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].entity(), third);
+
+        let mut query = Query::new(&entities);
+        let query_entities = query.with_component::<Health>()?.run_query();
+        query_entities[0].get_component_mut::<Health>()?.0 += 1;
+        // Only reading doesn't count as a change.
+        query_entities[1].get_component::<Health>()?;
+
+        let mut query = Query::new(&entities);
+        assert_eq!(query.changed::<Health>()?.indexes(), vec![0, 2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn run_query_in_stable_order() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        let first = entities.spawn((Rank(3),))?;
+        let second = entities.spawn((Rank(1), Letter('b')))?;
+        let third = entities.spawn((Rank(2), Letter('a')))?;
+        entities.despawn(first)?;
+        // Reuses the first slot, but comes last.
+        let fourth = entities.spawn((Rank(1),))?;
+        let fifth = entities.spawn((Rank(5), Letter('a')))?;
+        let order = |query: &Query| -> Vec<Entity> {
+            query
+                .run_query()
+                .iter()
+                .map(|entity| entity.entity())
+                .collect()
+        };
+
+        let mut query = Query::new(&entities);
+        query.with_component::<Rank>()?;
+        assert_eq!(order(&query), vec![second, third, fourth, fifth]);
+        assert_eq!(query.run()?.0, vec![1, 2, 0, 3]);
+
+        query.order_by::<Rank>();
+        assert_eq!(order(&query), vec![second, fourth, third, fifth]);
+
+        let mut query = Query::new(&entities);
+        query
+            .with_component::<Rank>()?
+            .order_by::<Letter>()
+            .order_by_key::<Rank, _>(|rank| std::cmp::Reverse(rank.0));
+        assert_eq!(order(&query), vec![fifth, third, second, fourth]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn run_rejects_table_components() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component_with::<Health>(StorageKind::Table);
+        entities.register_component::<Speed>();
+        entities.spawn((Health(10), Speed(1.0)))?;
+
+        let mut query = Query::new(&entities);
+        query.with_component::<Speed>()?.optional::<Health>();
+        assert_eq!(
+            query.run().err(),
+            Some(EcsError::UnsupportedStorage {
+                type_name: Health::TYPE_NAME
+            })
+        );
+        let query_entities = query.with_component::<Health>()?.run_query();
+        assert_eq!(query_entities[0].get_component::<Health>()?.0, 10);
+        let held = query_entities[0].get_component_mut::<Health>()?;
+        assert!(query_entities[0].get_component::<Health>().is_err());
+        drop(held);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,232 @@
+use std::{cell::Cell, collections::HashMap};
+
+use super::Component;
+
+/// Change ticks of one component: when it was added to the entity and when
+/// it was last inserted or mutably borrowed.
+#[derive(Debug, Clone)]
+pub struct ComponentTicks {
+    pub added: u32,
+    pub changed: Cell<u32>,
+}
+
+impl ComponentTicks {
+    pub fn new(tick: u32) -> Self {
+        Self {
+            added: tick,
+            changed: Cell::new(tick),
+        }
+    }
+
+    pub fn set_changed(&self, tick: u32) {
+        self.changed.set(tick);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Slot {
+    pub component: Component,
+    pub ticks: ComponentTicks,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StorageKind {
+    /// One slot per entity. Best for components most entities have.
+    #[default]
+    Dense,
+    /// Only entities that have the component take up space, and adding or
+    /// removing it is O(1). Best for rare or tag-like components.
+    SparseSet,
+    /// Packed in an archetype table with the entity's other `Table`
+    /// components, so queries over them are fastest. Adding or removing one
+    /// moves all of them, and `Query::run` fails with `UnsupportedStorage` for
+    /// them.
+    Table,
+}
+
+/// Packed components plus a map from entity index to their position.
+#[derive(Debug, Default)]
+pub struct SparseSet {
+    sparse: HashMap<usize, usize>,
+    dense: Vec<Slot>,
+    dense_indexes: Vec<usize>,
+}
+
+impl SparseSet {
+    fn get(&self, index: usize) -> Option<&Slot> {
+        self.sparse
+            .get(&index)
+            .map(|dense_index| &self.dense[*dense_index])
+    }
+
+    fn insert(&mut self, index: usize, slot: Slot) {
+        if let Some(dense_index) = self.sparse.get(&index) {
+            self.dense[*dense_index] = slot;
+        } else {
+            self.sparse.insert(index, self.dense.len());
+            self.dense.push(slot);
+            self.dense_indexes.push(index);
+        }
+    }
+
+    fn remove(&mut self, index: usize) -> Option<Slot> {
+        let dense_index = self.sparse.remove(&index)?;
+        let slot = self.dense.swap_remove(dense_index);
+        self.dense_indexes.swap_remove(dense_index);
+        if let Some(moved) = self.dense_indexes.get(dense_index) {
+            self.sparse.insert(*moved, dense_index);
+        }
+        Some(slot)
+    }
+}
+
+#[derive(Debug)]
+pub enum ComponentStorage {
+    Dense(Vec<Option<Slot>>),
+    SparseSet(SparseSet),
+    /// The components are in `Entities::tables`.
+    Table,
+}
+
+impl ComponentStorage {
+    pub fn new(kind: StorageKind, entity_count: usize) -> Self {
+        match kind {
+            StorageKind::Dense => Self::Dense(vec![None; entity_count]),
+            StorageKind::SparseSet => Self::SparseSet(SparseSet::default()),
+            StorageKind::Table => Self::Table,
+        }
+    }
+
+    pub fn kind(&self) -> StorageKind {
+        match self {
+            Self::Dense(_) => StorageKind::Dense,
+            Self::SparseSet(_) => StorageKind::SparseSet,
+            Self::Table => StorageKind::Table,
+        }
+    }
+
+    /// Makes room for a newly created entity.
+    pub fn push_empty(&mut self) {
+        if let Self::Dense(components) = self {
+            components.push(None);
+        }
+    }
+
+    pub fn get_slot(&self, index: usize) -> Option<&Slot> {
+        match self {
+            Self::Dense(components) => components.get(index)?.as_ref(),
+            Self::SparseSet(set) => set.get(index),
+            Self::Table => None,
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Component> {
+        self.get_slot(index).map(|slot| &slot.component)
+    }
+
+    pub fn get_ticks(&self, index: usize) -> Option<&ComponentTicks> {
+        self.get_slot(index).map(|slot| &slot.ticks)
+    }
+
+    /// Inserts `component` at `tick`. Replacing a component only marks it
+    /// changed; it keeps the tick it was added at.
+    pub fn insert(&mut self, index: usize, component: Component, tick: u32) {
+        let mut ticks = ComponentTicks::new(tick);
+        if let Some(replaced) = self.get_ticks(index) {
+            ticks.added = replaced.added;
+        }
+        self.insert_slot(index, Slot { component, ticks });
+    }
+
+    /// Like `insert`, but keeps the given ticks.
+    pub fn insert_slot(&mut self, index: usize, slot: Slot) {
+        match self {
+            Self::Dense(components) => components[index] = Some(slot),
+            Self::SparseSet(set) => set.insert(index, slot),
+            Self::Table => unreachable!("table components are inserted into `Tables`"),
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Component> {
+        let slot = match self {
+            Self::Dense(components) => components.get_mut(index)?.take(),
+            Self::SparseSet(set) => set.remove(index),
+            Self::Table => None,
+        };
+        slot.map(|slot| slot.component)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    fn component(value: u32) -> Component {
+        Rc::new(RefCell::new(value))
+    }
+
+    fn slot(value: u32) -> Slot {
+        Slot {
+            component: component(value),
+            ticks: ComponentTicks::new(0),
+        }
+    }
+
+    fn value(storage: &ComponentStorage, index: usize) -> Option<u32> {
+        storage
+            .get(index)
+            .map(|component| *component.borrow().downcast_ref::<u32>().unwrap())
+    }
+
+    #[test]
+    fn dense_storage_has_a_slot_per_entity() {
+        let mut storage = ComponentStorage::new(StorageKind::Dense, 2);
+        storage.push_empty();
+        storage.insert(1, component(5), 0);
+
+        assert!(matches!(&storage, ComponentStorage::Dense(components) if components.len() == 3));
+        assert_eq!(value(&storage, 1), Some(5));
+        assert_eq!(value(&storage, 0), None);
+        assert_eq!(value(&storage, 10), None);
+        assert!(storage.remove(1).is_some());
+        assert_eq!(value(&storage, 1), None);
+    }
+
+    #[test]
+    fn sparse_set_only_stores_present_components() {
+        let mut set = SparseSet::default();
+        set.insert(900, slot(1));
+        set.insert(3, slot(2));
+        set.insert(42, slot(3));
+        set.insert(3, slot(4));
+        assert_eq!(set.dense.len(), 3);
+
+        assert!(set.remove(900).is_some());
+        assert!(set.remove(900).is_none());
+        assert_eq!(set.dense.len(), 2);
+        assert_eq!(set.sparse.len(), 2);
+
+        let mut storage = ComponentStorage::new(StorageKind::SparseSet, 1000);
+        storage.push_empty();
+        assert!(matches!(&storage, ComponentStorage::SparseSet(set) if set.dense.is_empty()));
+        storage = ComponentStorage::SparseSet(set);
+        assert_eq!(value(&storage, 3), Some(4));
+        assert_eq!(value(&storage, 42), Some(3));
+        assert_eq!(value(&storage, 900), None);
+    }
+
+    #[test]
+    fn replacing_keeps_the_added_tick() {
+        let mut storage = ComponentStorage::new(StorageKind::SparseSet, 0);
+        storage.insert(0, component(1), 3);
+        storage.get_ticks(0).unwrap().set_changed(5);
+        assert_eq!(storage.get_ticks(0).unwrap().added, 3);
+        assert_eq!(storage.get_ticks(0).unwrap().changed.get(), 5);
+
+        storage.insert(0, component(2), 7);
+        assert_eq!(storage.get_ticks(0).unwrap().added, 3);
+        assert_eq!(storage.get_ticks(0).unwrap().changed.get(), 7);
+    }
+}
@@ -0,0 +1,409 @@
+//! Archetype tables behind `StorageKind::Table`. Entities with the same set
+//! of table components share a `Table`, whose columns hold those components
+//! packed in row order, so a query over them reads memory front to back
+//! instead of chasing a pointer per component. Adding or removing a table
+//! component moves the entity's row to the table of its new set.
+
+use std::{
+    any::{Any, TypeId},
+    cell::{Ref, RefCell, RefMut},
+    collections::HashMap,
+    fmt,
+    rc::Rc,
+};
+
+use super::{
+    storage::{ComponentTicks, Slot},
+    Component, ComponentCell,
+};
+
+/// One component type's values in a `Table`, a row each.
+pub trait Column: Any {
+    /// Empty column of the same type.
+    fn empty(&self) -> Box<dyn Column>;
+
+    fn len(&self) -> usize;
+
+    fn cell(&self, row: usize) -> &dyn ComponentCell;
+
+    fn ticks(&self, row: usize) -> &ComponentTicks;
+
+    /// Appends the component of `slot`, which must be of the column's type
+    /// and not shared.
+    fn push(&mut self, slot: Slot);
+
+    /// Swaps the value in `row` for `component`, leaving the ticks alone.
+    fn replace(&mut self, row: usize, component: Component);
+
+    fn swap_remove(&mut self, row: usize) -> Slot;
+
+    /// Swap-removes `row` and appends it to `to`, which holds the same type.
+    fn move_row(&mut self, row: usize, to: &mut dyn Column);
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl fmt::Debug for dyn Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries((0..self.len()).map(|row| self.cell(row)))
+            .finish()
+    }
+}
+
+/// The `T` components of a table. Each value keeps its own `RefCell`, so
+/// borrowing one entity's component doesn't lock the others.
+pub struct TableColumn<T> {
+    cells: Vec<RefCell<T>>,
+    ticks: Vec<ComponentTicks>,
+}
+
+impl<T: Any> TableColumn<T> {
+    pub fn new() -> Self {
+        Self {
+            cells: vec![],
+            ticks: vec![],
+        }
+    }
+
+    /// `None` if the value is mutably borrowed.
+    pub fn borrow(&self, row: usize) -> Option<Ref<'_, T>> {
+        self.cells[row].try_borrow().ok()
+    }
+
+    /// Like `borrow`, but also marks the value as changed at `tick`.
+    pub fn borrow_mut(&self, row: usize, tick: u32) -> Option<RefMut<'_, T>> {
+        let value = self.cells[row].try_borrow_mut().ok()?;
+        self.ticks[row].set_changed(tick);
+        Some(value)
+    }
+}
+
+impl<T: Any> Default for TableColumn<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn unwrap<T: Any>(component: Component) -> RefCell<T> {
+    let cell = component
+        .into_any()
+        .downcast::<RefCell<T>>()
+        .unwrap_or_else(|_| panic!("component pushed into another type's column"));
+    Rc::try_unwrap(cell).unwrap_or_else(|_| panic!("table components can't be shared"))
+}
+
+impl<T: Any> Column for TableColumn<T> {
+    fn empty(&self) -> Box<dyn Column> {
+        Box::new(Self::new())
+    }
+
+    fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn cell(&self, row: usize) -> &dyn ComponentCell {
+        &self.cells[row]
+    }
+
+    fn ticks(&self, row: usize) -> &ComponentTicks {
+        &self.ticks[row]
+    }
+
+    fn push(&mut self, slot: Slot) {
+        self.cells.push(unwrap(slot.component));
+        self.ticks.push(slot.ticks);
+    }
+
+    fn replace(&mut self, row: usize, component: Component) {
+        self.cells[row] = unwrap(component);
+    }
+
+    fn swap_remove(&mut self, row: usize) -> Slot {
+        Slot {
+            component: Rc::new(self.cells.swap_remove(row)),
+            ticks: self.ticks.swap_remove(row),
+        }
+    }
+
+    fn move_row(&mut self, row: usize, to: &mut dyn Column) {
+        let to = to.as_any_mut().downcast_mut::<Self>().unwrap();
+        to.cells.push(self.cells.swap_remove(row));
+        to.ticks.push(self.ticks.swap_remove(row));
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Entities that have exactly the same table components.
+#[derive(Debug, Default)]
+pub struct Table {
+    /// Sorted, so a set of types has one key in `Tables::by_type_ids`.
+    type_ids: Vec<TypeId>,
+    columns: HashMap<TypeId, Box<dyn Column>>,
+    /// Entity slot index of every row.
+    indexes: Vec<usize>,
+}
+
+impl Table {
+    /// Entity slot indexes, in row order.
+    pub fn indexes(&self) -> &[usize] {
+        &self.indexes
+    }
+
+    pub fn column<T: Any>(&self) -> Option<&TableColumn<T>> {
+        self.columns
+            .get(&TypeId::of::<T>())?
+            .as_any()
+            .downcast_ref()
+    }
+}
+
+/// Where an entity's table components are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Row {
+    pub table: usize,
+    pub row: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Tables {
+    tables: Vec<Table>,
+    by_type_ids: HashMap<Vec<TypeId>, usize>,
+    /// Per entity slot, `None` while the entity has no table components.
+    rows: Vec<Option<Row>>,
+}
+
+impl Tables {
+    /// Makes room for a newly created entity slot.
+    pub fn push_slot(&mut self) {
+        self.rows.push(None);
+    }
+
+    pub fn row(&self, index: usize) -> Option<Row> {
+        self.rows.get(index).copied().flatten()
+    }
+
+    pub fn table(&self, table: usize) -> &Table {
+        &self.tables[table]
+    }
+
+    /// Tables with every type in `type_ids`, oldest first.
+    pub fn matching<'a>(&'a self, type_ids: &'a [TypeId]) -> impl Iterator<Item = &'a Table> {
+        self.tables.iter().filter(|table| {
+            type_ids
+                .iter()
+                .all(|type_id| table.columns.contains_key(type_id))
+        })
+    }
+
+    pub fn get(
+        &self,
+        type_id: &TypeId,
+        index: usize,
+    ) -> Option<(&dyn ComponentCell, &ComponentTicks)> {
+        let row = self.row(index)?;
+        let column = self.tables[row.table].columns.get(type_id)?;
+        Some((column.cell(row.row), column.ticks(row.row)))
+    }
+
+    /// Inserts `component` at `tick`. Replacing a component only marks it
+    /// changed; it keeps the tick it was added at.
+    pub fn insert(&mut self, index: usize, type_id: TypeId, component: Component, tick: u32) {
+        if let Some(row) = self.row(index) {
+            if let Some(column) = self.tables[row.table].columns.get_mut(&type_id) {
+                column.replace(row.row, component);
+                column.ticks(row.row).set_changed(tick);
+                return;
+            }
+        }
+        let slot = Slot {
+            component,
+            ticks: ComponentTicks::new(tick),
+        };
+        self.insert_slot(index, type_id, slot);
+    }
+
+    /// Like `insert`, but keeps the given ticks. `index` mustn't have a
+    /// `type_id` component yet.
+    pub fn insert_slot(&mut self, index: usize, type_id: TypeId, slot: Slot) {
+        let from = self.row(index).map(|row| row.table);
+        let mut type_ids = from
+            .map(|from| self.tables[from].type_ids.clone())
+            .unwrap_or_default();
+        type_ids.push(type_id);
+        type_ids.sort_unstable();
+        let column = slot.component.new_column();
+        let to = self.table_for(type_ids, from, Some((type_id, column)));
+        self.move_row(index, Some(to));
+        self.tables[to]
+            .columns
+            .get_mut(&type_id)
+            .unwrap()
+            .push(slot);
+    }
+
+    pub fn remove(&mut self, index: usize, type_id: TypeId) -> Option<Component> {
+        let from = self.row(index)?.table;
+        let table = &self.tables[from];
+        if !table.columns.contains_key(&type_id) {
+            return None;
+        }
+        let type_ids: Vec<TypeId> = table
+            .type_ids
+            .iter()
+            .copied()
+            .filter(|other| *other != type_id)
+            .collect();
+        let to = (!type_ids.is_empty()).then(|| self.table_for(type_ids, Some(from), None));
+        self.move_row(index, to)
+            .into_iter()
+            .find(|(removed, _)| *removed == type_id)
+            .map(|(_, slot)| slot.component)
+    }
+
+    /// Drops every table component of `index`, returning their types.
+    pub fn remove_all(&mut self, index: usize) -> Vec<TypeId> {
+        self.move_row(index, None)
+            .into_iter()
+            .map(|(type_id, _)| type_id)
+            .collect()
+    }
+
+    /// Table for `type_ids`, made with the columns of `from` it shares plus
+    /// `added` if there isn't one yet.
+    fn table_for(
+        &mut self,
+        type_ids: Vec<TypeId>,
+        from: Option<usize>,
+        added: Option<(TypeId, Box<dyn Column>)>,
+    ) -> usize {
+        if let Some(table) = self.by_type_ids.get(&type_ids) {
+            return *table;
+        }
+        let mut columns: HashMap<TypeId, Box<dyn Column>> = from
+            .into_iter()
+            .flat_map(|from| &self.tables[from].columns)
+            .filter(|(type_id, _)| type_ids.contains(type_id))
+            .map(|(type_id, column)| (*type_id, column.empty()))
+            .collect();
+        columns.extend(added);
+        self.tables.push(Table {
+            type_ids: type_ids.clone(),
+            columns,
+            indexes: vec![],
+        });
+        self.by_type_ids.insert(type_ids, self.tables.len() - 1);
+        self.tables.len() - 1
+    }
+
+    /// Moves the row of `index` to table `to`, or out of every table.
+    /// Returns the components `to` has no column for.
+    fn move_row(&mut self, index: usize, to: Option<usize>) -> Vec<(TypeId, Slot)> {
+        let mut left = vec![];
+        if let Some(from) = self.row(index) {
+            let mut source = std::mem::take(&mut self.tables[from.table]);
+            for (type_id, column) in &mut source.columns {
+                let target = to.and_then(|to| self.tables[to].columns.get_mut(type_id));
+                match target {
+                    Some(target) => column.move_row(from.row, &mut **target),
+                    None => left.push((*type_id, column.swap_remove(from.row))),
+                }
+            }
+            source.indexes.swap_remove(from.row);
+            if let Some(moved) = source.indexes.get(from.row) {
+                self.rows[*moved] = Some(from);
+            }
+            self.tables[from.table] = source;
+        }
+        self.rows[index] = to.map(|to| {
+            let table = &mut self.tables[to];
+            table.indexes.push(index);
+            Row {
+                table: to,
+                row: table.indexes.len() - 1,
+            }
+        });
+        left
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn component(value: u32) -> Component {
+        Rc::new(RefCell::new(value))
+    }
+
+    fn value(tables: &Tables, type_id: TypeId, index: usize) -> Option<u32> {
+        let (cell, _) = tables.get(&type_id, index)?;
+        let value = cell.borrow();
+        Some(*value.downcast_ref::<u32>().unwrap())
+    }
+
+    #[test]
+    fn rows_move_between_tables() {
+        let number = TypeId::of::<u32>();
+        let flag = TypeId::of::<bool>();
+        let mut tables = Tables::default();
+        (0..3).for_each(|_| tables.push_slot());
+        for index in 0..3 {
+            tables.insert(index, number, component(index as u32), 0);
+        }
+        tables.insert_slot(
+            0,
+            flag,
+            Slot {
+                component: Rc::new(RefCell::new(true)),
+                ticks: ComponentTicks::new(0),
+            },
+        );
+
+        // {u32} and {u32, bool}; the last row of {u32} filled the hole.
+        assert_eq!(tables.tables.len(), 2);
+        assert_eq!(tables.table(0).indexes(), &[2, 1]);
+        assert_eq!(tables.row(2), Some(Row { table: 0, row: 0 }));
+        assert_eq!(value(&tables, number, 0), Some(0));
+        assert_eq!(value(&tables, number, 2), Some(2));
+
+        tables.insert(2, number, component(20), 1);
+        assert_eq!(value(&tables, number, 2), Some(20));
+        let (_, ticks) = tables.get(&number, 2).unwrap();
+        assert_eq!((ticks.added, ticks.changed.get()), (0, 1));
+
+        let removed = tables.remove(0, number).unwrap();
+        assert_eq!(*removed.borrow().downcast_ref::<u32>().unwrap(), 0);
+        assert!(tables.remove(0, number).is_none());
+        assert_eq!(tables.row(0), Some(Row { table: 2, row: 0 }));
+        assert_eq!(tables.remove_all(0), vec![flag]);
+        assert_eq!(tables.row(0), None);
+        assert_eq!(value(&tables, number, 1), Some(1));
+    }
+
+    #[test]
+    fn typed_columns_borrow_per_row() {
+        let mut tables = Tables::default();
+        (0..2).for_each(|_| tables.push_slot());
+        tables.insert(0, TypeId::of::<u32>(), component(1), 0);
+        tables.insert(1, TypeId::of::<u32>(), component(2), 0);
+
+        let column = tables.table(0).column::<u32>().unwrap();
+        assert!(tables.table(0).column::<bool>().is_none());
+        let mut first = column.borrow_mut(0, 3).unwrap();
+        *first += 10;
+        assert!(column.borrow(0).is_none());
+        assert_eq!(*column.borrow(1).unwrap(), 2);
+        drop(first);
+        assert_eq!(*column.borrow(0).unwrap(), 11);
+        assert_eq!(column.ticks(0).changed.get(), 3);
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+};
+
+use super::{storage::StorageKind, Component, EcsError, Entities, Entity};
+
+/// Old and new handles of entities moved by `World::transfer_entity`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EntityMap {
+    map: HashMap<Entity, Entity>,
+}
+
+impl EntityMap {
+    /// New handle of `entity`, if it was moved.
+    pub fn get(&self, entity: Entity) -> Option<Entity> {
+        self.map.get(&entity).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+/// Opt-in for components holding `Entity` handles, e.g. a target or the
+/// owner of an item, so they can be pointed at the moved entities.
+pub trait MapEntities: Any {
+    /// Replaces every handle `map` has a new one for. Others are left to
+    /// the component, e.g. to clear a target that stayed behind.
+    fn map_entities(&mut self, map: &EntityMap);
+}
+
+type MapFn = fn(&mut dyn Any, &EntityMap);
+
+fn map_entities<T: MapEntities>(component: &mut dyn Any, map: &EntityMap) {
+    component.downcast_mut::<T>().unwrap().map_entities(map);
+}
+
+/// Components registered with `World::register_entity_refs`.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct EntityMappers {
+    mappers: HashMap<TypeId, MapFn>,
+}
+
+impl EntityMappers {
+    pub fn add<T: MapEntities>(&mut self) {
+        self.mappers.insert(TypeId::of::<T>(), map_entities::<T>);
+    }
+
+    /// Remaps `type_id` components like `from` does, unless they are
+    /// remapped already.
+    pub fn copy(&mut self, from: &EntityMappers, type_id: TypeId) {
+        if let Some(map_entities) = from.mappers.get(&type_id) {
+            self.mappers.entry(type_id).or_insert(*map_entities);
+        }
+    }
+
+    /// Remaps the handles in every component of the moved entities. They
+    /// passed `Entities::check_take`, so nothing else can be borrowing them.
+    pub fn apply(&self, entities: &Entities, map: &EntityMap) {
+        for entity in map.map.values() {
+            for (type_id, _, component) in entities.components_of(*entity) {
+                if let Some(map_entities) = self.mappers.get(&type_id) {
+                    let mut component = component
+                        .try_borrow_mut()
+                        .expect("moved components aren't shared");
+                    map_entities(&mut *component, map);
+                }
+            }
+        }
+    }
+}
+
+/// An entity taken out by `Entities::take`.
+#[derive(Debug)]
+pub(crate) struct TakenEntity {
+    entity: Entity,
+    parent: Option<Entity>,
+    components: Vec<(TypeId, &'static str, StorageKind, Component)>,
+}
+
+impl TakenEntity {
+    pub fn type_ids(&self) -> impl Iterator<Item = TypeId> + '_ {
+        self.components.iter().map(|(type_id, ..)| *type_id)
+    }
+}
+
+impl Entities {
+    /// Whether `take` would succeed: fails if a component of `entity` or
+    /// its descendants is still shared by the result of a `Query::run`,
+    /// which would otherwise alias a component owned by another world. Once
+    /// this passes, nothing but the world itself can borrow them.
+    pub(crate) fn check_take(&self, entity: Entity) -> Result<(), EcsError> {
+        if !self.is_alive(entity) {
+            return Err(EcsError::StaleEntity { entity });
+        }
+        for taken in std::iter::once(entity).chain(self.descendants(entity)) {
+            for (type_id, type_name, _) in self.components_of(taken) {
+                if self.is_shared(&type_id, taken.index) {
+                    return Err(EcsError::AlreadyBorrowed {
+                        entity: taken,
+                        type_name,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Despawns `entity` and its descendants, handing over their components
+    /// instead of dropping them. Parents come before their children.
+    pub(crate) fn take(&mut self, entity: Entity) -> Result<Vec<TakenEntity>, EcsError> {
+        self.check_take(entity)?;
+        let mut taken = vec![];
+        for moved in std::iter::once(entity).chain(self.descendants(entity)) {
+            let parent = self.parent(moved).filter(|_| moved != entity);
+            let names: Vec<(TypeId, &'static str)> = self
+                .components_of(moved)
+                .into_iter()
+                .map(|(type_id, type_name, _)| (type_id, type_name))
+                .collect();
+            let components = names
+                .into_iter()
+                .filter_map(|(type_id, type_name)| {
+                    let kind = self.components[&type_id].kind();
+                    let component = self.remove_raw(moved, type_id)?;
+                    Some((type_id, type_name, kind, component))
+                })
+                .collect();
+            taken.push(TakenEntity {
+                entity: moved,
+                parent,
+                components,
+            });
+        }
+        self.despawn(entity)?;
+        Ok(taken)
+    }
+
+    /// Spawns the entities from `take`, registering their components where
+    /// needed and keeping their hierarchy.
+    pub(crate) fn put(&mut self, taken: Vec<TakenEntity>) -> EntityMap {
+        let mut map = EntityMap::default();
+        for taken in &taken {
+            let entity = self.create_entity().current_entity();
+            map.map.insert(taken.entity, entity);
+        }
+        for taken in taken {
+            let entity = map.map[&taken.entity];
+            for (type_id, type_name, kind, component) in taken.components {
+                self.register_raw(type_id, kind, type_name);
+                self.insert_raw(entity, type_id, component);
+            }
+            if let Some(parent) = taken.parent {
+                self.set_parent(entity, map.map[&parent])
+                    .expect("parents are spawned before their children");
+            }
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ecs::{Component, World};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Component)]
+    struct Health(pub u32);
+    #[derive(Component)]
+    struct Player;
+    /// Who carries an item.
+    #[derive(Component)]
+    struct Owner(pub Entity);
+    /// Who a monster is chasing, if anyone in its world.
+    #[derive(Component)]
+    struct Target(pub Option<Entity>);
+
+    impl MapEntities for Owner {
+        fn map_entities(&mut self, map: &EntityMap) {
+            self.0 = map.get(self.0).unwrap_or(self.0);
+        }
+    }
+
+    impl MapEntities for Target {
+        fn map_entities(&mut self, map: &EntityMap) {
+            self.0 = self.0.and_then(|target| map.get(target));
+        }
+    }
+
+    #[test]
+    fn transfer_moves_descendants_and_remaps_handles() -> Result<(), EcsError> {
+        let mut level = World::new();
+        level.register_component_with::<Player>(StorageKind::SparseSet);
+        level.register_entity_refs::<Owner>();
+        level.register_entity_refs::<Target>();
+        let monster = level.spawn((Health(5),))?;
+        let player = level.spawn((Health(10), Player, Target(Some(monster))))?;
+        let backpack = level.spawn((Owner(player),))?;
+        let potion = level.spawn((Owner(player), Health(1)))?;
+        level.set_parent(backpack, player)?;
+        level.set_parent(potion, backpack)?;
+
+        let mut next_level = World::new();
+        next_level.spawn((Health(7),))?;
+        next_level.on_add::<Health>(|world, entity| {
+            // Hooks see the remapped handles.
+            assert!(world.entity(entity).unwrap().get::<Health>().is_ok());
+        });
+        let moved = level.transfer_entity(player, &mut next_level)?;
+
+        assert!(!level.is_alive(player));
+        assert!(!level.is_alive(potion));
+        assert!(level.is_alive(monster));
+        assert_eq!(level.query::<&Health>().iter()?.count(), 1);
+
+        let moved_ref = next_level.entity(moved)?;
+        assert_eq!(*moved_ref.get::<Health>()?, Health(10));
+        assert!(moved_ref.get::<Target>()?.0.is_none());
+        assert_eq!(
+            next_level.storage_kind::<Player>(),
+            Some(StorageKind::SparseSet)
+        );
+        let [moved_backpack] = next_level.children(moved) else {
+            panic!("backpack wasn't moved along");
+        };
+        let [moved_potion] = next_level.children(*moved_backpack) else {
+            panic!("potion wasn't moved along");
+        };
+        let potion_ref = next_level.entity(*moved_potion)?;
+        assert_eq!(potion_ref.get::<Owner>()?.0, moved);
+        assert_eq!(next_level.query::<&Health>().iter()?.count(), 3);
+
+        assert_eq!(
+            level.transfer_entity(player, &mut next_level),
+            Err(EcsError::StaleEntity { entity: player })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn transfer_fails_while_components_are_shared() -> Result<(), EcsError> {
+        let mut level = World::new();
+        level.register_entity_refs::<Owner>();
+        let player = level.spawn((Health(10),))?;
+        let potion = level.spawn((Owner(player), Health(1)))?;
+        level.set_parent(potion, player)?;
+        let mut next_level = World::new();
+
+        // `Health` has no entity refs, but a `Query::run` result holding on
+        // to it would alias a component owned by `next_level`.
+        let mut query = level.dynamic_query();
+        query.with_component::<Health>()?;
+        let (_, healths, _) = query.run()?;
+        assert_eq!(
+            level.transfer_entity(player, &mut next_level),
+            Err(EcsError::AlreadyBorrowed {
+                entity: player,
+                type_name: Health::TYPE_NAME
+            })
+        );
+        assert!(level.is_alive(player));
+        assert!(level.is_alive(potion));
+        assert_eq!(next_level.query::<Entity>().iter()?.count(), 0);
+
+        drop(healths);
+        let moved = level.transfer_entity(player, &mut next_level)?;
+        let [moved_potion] = next_level.children(moved) else {
+            panic!("potion wasn't moved along");
+        };
+        assert_eq!(next_level.entity(*moved_potion)?.get::<Owner>()?.0, moved);
+
+        Ok(())
+    }
+
+    /// How often an `Owner` was removed.
+    struct Removed(pub u32);
+
+    #[test]
+    fn failed_transfer_leaves_both_worlds_alone() -> Result<(), EcsError> {
+        let mut level = World::new();
+        level.register_entity_refs::<Owner>();
+        level.add_resource(Removed(0));
+        level.on_remove::<Owner>(|world, _| world.get_resource_mut::<Removed>().unwrap().0 += 1);
+        let player = level.spawn((Health(10),))?;
+        let potion = level.spawn((Owner(player),))?;
+        level.set_parent(potion, player)?;
+        let mut next_level = World::new();
+
+        let mut query = level.dynamic_query();
+        query.with_component::<Owner>()?;
+        let (_, owners, _) = query.run()?;
+        assert_eq!(
+            level.transfer_entity(player, &mut next_level),
+            Err(EcsError::AlreadyBorrowed {
+                entity: potion,
+                type_name: Owner::TYPE_NAME
+            })
+        );
+        assert_eq!(level.get_resource::<Removed>().unwrap().0, 0);
+        assert_eq!(level.entity(potion)?.get::<Owner>()?.0, player);
+        assert!(next_level.query::<Entity>().iter()?.next().is_none());
+        drop(owners);
+
+        Ok(())
+    }
+}
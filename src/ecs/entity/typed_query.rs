@@ -0,0 +1,654 @@
+use std::{
+    any::TypeId,
+    cell::{Ref, RefMut},
+    collections::HashSet,
+    marker::PhantomData,
+};
+
+use super::{
+    super::Component,
+    query::Query,
+    table::{Table, TableColumn},
+    EcsError, Entities, Entity,
+};
+
+/// Something a `TypedQuery` can fetch for every matching entity: a component
+/// reference, the entity handle, or a tuple of those.
+pub trait QueryData {
+    type Item<'a>;
+
+    /// The columns of one table that `fetch` reads instead of looking each
+    /// component up.
+    type Columns<'a>;
+
+    /// Records the component types this borrows, failing if a `&mut T`
+    /// aliases another borrow of `T`.
+    fn access(access: &mut QueryAccess) -> Result<(), EcsError>;
+
+    fn add_to(query: &mut Query) -> Result<(), EcsError>;
+
+    /// Looks up the columns in `table`, the table of the entities about to
+    /// be fetched, if they are in one.
+    fn columns(table: Option<&Table>) -> Self::Columns<'_>;
+
+    /// Fetches the entity in slot `index`, whose table row is `row`.
+    fn fetch<'a>(
+        entities: &'a Entities,
+        columns: &Self::Columns<'a>,
+        index: usize,
+        row: usize,
+    ) -> Result<Self::Item<'a>, EcsError>;
+}
+
+fn already_borrowed<T: Component>(entities: &Entities, index: usize) -> EcsError {
+    EcsError::AlreadyBorrowed {
+        entity: entities.entity_at(index),
+        type_name: T::TYPE_NAME,
+    }
+}
+
+/// Component types borrowed by a `QueryData`, shared or mutably.
+#[derive(Debug, Default)]
+pub struct QueryAccess {
+    reads: HashSet<TypeId>,
+    writes: HashSet<TypeId>,
+}
+
+impl QueryAccess {
+    pub fn read<T: Component>(&mut self) -> Result<(), EcsError> {
+        let type_id = TypeId::of::<T>();
+        if self.writes.contains(&type_id) {
+            return Err(EcsError::AliasedQuery {
+                type_name: T::TYPE_NAME,
+            });
+        }
+        self.reads.insert(type_id);
+        Ok(())
+    }
+
+    pub fn write<T: Component>(&mut self) -> Result<(), EcsError> {
+        let type_id = TypeId::of::<T>();
+        if self.reads.contains(&type_id) || !self.writes.insert(type_id) {
+            return Err(EcsError::AliasedQuery {
+                type_name: T::TYPE_NAME,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<T: Component> QueryData for &T {
+    type Item<'a> = Ref<'a, T>;
+    type Columns<'a> = Option<&'a TableColumn<T>>;
+
+    fn access(access: &mut QueryAccess) -> Result<(), EcsError> {
+        access.read::<T>()
+    }
+
+    fn add_to(query: &mut Query) -> Result<(), EcsError> {
+        query.with_component::<T>()?;
+        Ok(())
+    }
+
+    fn columns(table: Option<&Table>) -> Self::Columns<'_> {
+        table?.column::<T>()
+    }
+
+    fn fetch<'a>(
+        entities: &'a Entities,
+        columns: &Self::Columns<'a>,
+        index: usize,
+        row: usize,
+    ) -> Result<Self::Item<'a>, EcsError> {
+        match columns {
+            Some(column) => column
+                .borrow(row)
+                .ok_or_else(|| already_borrowed::<T>(entities, index)),
+            None => entities.borrow_at::<T>(index),
+        }
+    }
+}
+
+impl<T: Component> QueryData for &mut T {
+    type Item<'a> = RefMut<'a, T>;
+    type Columns<'a> = Option<&'a TableColumn<T>>;
+
+    fn access(access: &mut QueryAccess) -> Result<(), EcsError> {
+        access.write::<T>()
+    }
+
+    fn add_to(query: &mut Query) -> Result<(), EcsError> {
+        query.with_component::<T>()?;
+        Ok(())
+    }
+
+    fn columns(table: Option<&Table>) -> Self::Columns<'_> {
+        table?.column::<T>()
+    }
+
+    fn fetch<'a>(
+        entities: &'a Entities,
+        columns: &Self::Columns<'a>,
+        index: usize,
+        row: usize,
+    ) -> Result<Self::Item<'a>, EcsError> {
+        match columns {
+            Some(column) => column
+                .borrow_mut(row, entities.change_tick())
+                .ok_or_else(|| already_borrowed::<T>(entities, index)),
+            None => entities.borrow_mut_at::<T>(index),
+        }
+    }
+}
+
+impl<T: Component> QueryData for Option<&T> {
+    type Item<'a> = Option<Ref<'a, T>>;
+    type Columns<'a> = Option<&'a TableColumn<T>>;
+
+    fn access(access: &mut QueryAccess) -> Result<(), EcsError> {
+        access.read::<T>()
+    }
+
+    fn add_to(query: &mut Query) -> Result<(), EcsError> {
+        query.optional::<T>();
+        Ok(())
+    }
+
+    fn columns(table: Option<&Table>) -> Self::Columns<'_> {
+        table?.column::<T>()
+    }
+
+    fn fetch<'a>(
+        entities: &'a Entities,
+        columns: &Self::Columns<'a>,
+        index: usize,
+        row: usize,
+    ) -> Result<Self::Item<'a>, EcsError> {
+        if columns.is_some() {
+            return <&T>::fetch(entities, columns, index, row).map(Some);
+        }
+        match entities.borrow_at::<T>(index) {
+            Ok(component) => Ok(Some(component)),
+            Err(EcsError::ComponentNotRegistered { .. } | EcsError::ComponentMissing { .. }) => {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl<T: Component> QueryData for Option<&mut T> {
+    type Item<'a> = Option<RefMut<'a, T>>;
+    type Columns<'a> = Option<&'a TableColumn<T>>;
+
+    fn access(access: &mut QueryAccess) -> Result<(), EcsError> {
+        access.write::<T>()
+    }
+
+    fn add_to(query: &mut Query) -> Result<(), EcsError> {
+        query.optional::<T>();
+        Ok(())
+    }
+
+    fn columns(table: Option<&Table>) -> Self::Columns<'_> {
+        table?.column::<T>()
+    }
+
+    fn fetch<'a>(
+        entities: &'a Entities,
+        columns: &Self::Columns<'a>,
+        index: usize,
+        row: usize,
+    ) -> Result<Self::Item<'a>, EcsError> {
+        if columns.is_some() {
+            return <&mut T>::fetch(entities, columns, index, row).map(Some);
+        }
+        match entities.borrow_mut_at::<T>(index) {
+            Ok(component) => Ok(Some(component)),
+            Err(EcsError::ComponentNotRegistered { .. } | EcsError::ComponentMissing { .. }) => {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl QueryData for Entity {
+    type Item<'a> = Entity;
+    type Columns<'a> = ();
+
+    fn access(_access: &mut QueryAccess) -> Result<(), EcsError> {
+        Ok(())
+    }
+
+    fn add_to(_query: &mut Query) -> Result<(), EcsError> {
+        Ok(())
+    }
+
+    fn columns(_table: Option<&Table>) -> Self::Columns<'_> {}
+
+    fn fetch<'a>(
+        entities: &'a Entities,
+        _columns: &Self::Columns<'a>,
+        index: usize,
+        _row: usize,
+    ) -> Result<Self::Item<'a>, EcsError> {
+        Ok(entities.entity_at(index))
+    }
+}
+
+macro_rules! impl_query_data_for_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: QueryData),+> QueryData for ($($name,)+) {
+            type Item<'a> = ($($name::Item<'a>,)+);
+            type Columns<'a> = ($($name::Columns<'a>,)+);
+
+            fn access(access: &mut QueryAccess) -> Result<(), EcsError> {
+                $($name::access(access)?;)+
+                Ok(())
+            }
+
+            fn add_to(query: &mut Query) -> Result<(), EcsError> {
+                $($name::add_to(query)?;)+
+                Ok(())
+            }
+
+            fn columns(table: Option<&Table>) -> Self::Columns<'_> {
+                ($($name::columns(table),)+)
+            }
+
+            #[allow(non_snake_case)]
+            fn fetch<'a>(
+                entities: &'a Entities,
+                columns: &Self::Columns<'a>,
+                index: usize,
+                row: usize,
+            ) -> Result<Self::Item<'a>, EcsError> {
+                let ($($name,)+) = columns;
+                Ok(($($name::fetch(entities, $name, index, row)?,)+))
+            }
+        }
+    };
+}
+
+impl_query_data_for_tuple!(A);
+impl_query_data_for_tuple!(A, B);
+impl_query_data_for_tuple!(A, B, C);
+impl_query_data_for_tuple!(A, B, C, D);
+impl_query_data_for_tuple!(A, B, C, D, E);
+impl_query_data_for_tuple!(A, B, C, D, E, F);
+impl_query_data_for_tuple!(A, B, C, D, E, F, G);
+impl_query_data_for_tuple!(A, B, C, D, E, F, G, H);
+
+/// Query whose component types are part of its type, e.g.
+/// `TypedQuery<(&Position, &mut Renderable)>`. Entities are yielded oldest
+/// first, or as sorted by `order_by`.
+#[derive(Debug)]
+pub struct TypedQuery<'a, Q: QueryData> {
+    query: Query<'a>,
+    /// First error hit while building the query, returned by `iter`.
+    error: Option<EcsError>,
+    marker: PhantomData<Q>,
+}
+
+impl<'a, Q: QueryData> TypedQuery<'a, Q> {
+    pub fn new(entities: &'a Entities) -> Self {
+        let mut query = Query::new(entities);
+        let error = Q::access(&mut QueryAccess::default())
+            .and_then(|_| Q::add_to(&mut query))
+            .err();
+        Self {
+            query,
+            error,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn without<T: Component>(mut self) -> Self {
+        self.query.without::<T>();
+        self
+    }
+
+    pub fn any_of<T: Component>(mut self) -> Self {
+        self.query.any_of::<T>();
+        self
+    }
+
+    /// Only yields entities that got their `T` since the last
+    /// `clear_trackers`, not ones whose `T` was replaced.
+    pub fn added<T: Component>(mut self) -> Self {
+        if let Err(err) = self.query.added::<T>() {
+            self.error.get_or_insert(err);
+        }
+        self
+    }
+
+    /// Only yields entities whose `T` was inserted or mutably borrowed since
+    /// the last `clear_trackers`.
+    pub fn changed<T: Component>(mut self) -> Self {
+        if let Err(err) = self.query.changed::<T>() {
+            self.error.get_or_insert(err);
+        }
+        self
+    }
+
+    /// Only yields direct children of `parent`.
+    pub fn children_of(mut self, parent: Entity) -> Self {
+        self.query.children_of(parent);
+        self
+    }
+
+    /// Yields entities sorted by their `T`; see `Query::order_by`.
+    pub fn order_by<T: Component + Ord>(mut self) -> Self {
+        self.query.order_by::<T>();
+        self
+    }
+
+    /// Yields entities sorted by `key` of their `T`; see
+    /// `Query::order_by_key`.
+    pub fn order_by_key<T: Component, K: Ord>(mut self, key: impl Fn(&T) -> K + 'static) -> Self {
+        self.query.order_by_key(key);
+        self
+    }
+
+    /// Fails with `AliasedQuery` if `Q` borrows a component mutably together
+    /// with another borrow of it, e.g. `(&mut Health, &Health)`, and with
+    /// `ComponentNotRegistered` if a component it requires was never
+    /// registered. Items fail with `AlreadyBorrowed` if a component is
+    /// borrowed elsewhere.
+    pub fn iter(
+        &self,
+    ) -> Result<impl Iterator<Item = Result<Q::Item<'a>, EcsError>> + '_, EcsError> {
+        if let Some(err) = &self.error {
+            return Err(err.clone());
+        }
+        let entities = self.query.entities;
+        let tables = entities.tables();
+        let indexes = self.query.indexes();
+        // Matches mostly come a whole table at a time, so the columns are
+        // only looked up again when the table changes.
+        let mut columns: Option<(Option<usize>, Q::Columns<'a>)> = None;
+        Ok(indexes.into_iter().map(move |index| {
+            let row = tables.row(index);
+            let table = row.map(|row| row.table);
+            if columns
+                .as_ref()
+                .is_none_or(|(current, _)| *current != table)
+            {
+                columns = Some((table, Q::columns(table.map(|table| tables.table(table)))));
+            }
+            let (_, columns) = columns.as_ref().unwrap();
+            Q::fetch(entities, columns, index, row.map_or(0, |row| row.row))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ecs::Component;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Component)]
+    struct Health(pub u32);
+    #[derive(Component)]
+    struct Speed(pub u32);
+    #[derive(Component)]
+    struct Unregistered;
+
+    fn initialize_entities() -> Result<Entities, EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component::<Health>();
+        entities.register_component::<Speed>();
+        entities
+            .create_entity()
+            .with_component(Health(100))?
+            .with_component(Speed(15))?;
+        entities.create_entity().with_component(Health(40))?;
+        entities
+            .create_entity()
+            .with_component(Health(10))?
+            .with_component(Speed(50))?;
+        Ok(entities)
+    }
+
+    #[test]
+    fn typed_query_yields_tuples() -> Result<(), EcsError> {
+        let entities = initialize_entities()?;
+        let query = TypedQuery::<(&Health, &Speed)>::new(&entities);
+
+        let results: Vec<(u32, u32)> = query
+            .iter()?
+            .map(|item| item.map(|(health, speed)| (health.0, speed.0)))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(results, vec![(100, 15), (10, 50)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn typed_query_walks_tables_in_spawn_order() -> Result<(), EcsError> {
+        let mut entities = Entities::default();
+        entities.register_component_with::<Health>(super::super::storage::StorageKind::Table);
+        entities.register_component::<Speed>();
+        let first = entities.spawn((Health(100), Speed(15)))?;
+        entities.spawn((Health(40),))?;
+        let third = entities.spawn((Health(10), Speed(50)))?;
+        // Moves `first` to the end of its table.
+        entities.remove_component::<Health>(first);
+        entities.insert_component(first, Health(1))?;
+
+        for item in TypedQuery::<(&mut Health, &Speed)>::new(&entities).iter()? {
+            let (mut health, speed) = item?;
+            health.0 += speed.0;
+        }
+
+        let results: Vec<(Entity, u32)> = TypedQuery::<(Entity, &Health)>::new(&entities)
+            .iter()?
+            .map(|item| item.map(|(entity, health)| (entity, health.0)))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0], (first, 16));
+        assert_eq!(results[2], (third, 60));
+        assert_eq!(results[1].1, 40);
+
+        Ok(())
+    }
+
+    #[test]
+    fn typed_query_mutates_components() -> Result<(), EcsError> {
+        let entities = initialize_entities()?;
+
+        for item in TypedQuery::<(&mut Health, &Speed)>::new(&entities).iter()? {
+            let (mut health, speed) = item?;
+            health.0 += speed.0;
+        }
+
+        let healths: Vec<u32> = TypedQuery::<&Health>::new(&entities)
+            .iter()?
+            .map(|item| item.map(|health| health.0))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(healths, vec![115, 40, 60]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn typed_query_fetches_entity_handles() -> Result<(), EcsError> {
+        let entities = initialize_entities()?;
+        let query = TypedQuery::<(Entity, &Speed)>::new(&entities);
+
+        let indexes: Vec<usize> = query
+            .iter()?
+            .map(|item| item.map(|(entity, _)| entity.index))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(indexes, vec![0, 2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn typed_query_with_unregistered_component_fails() -> Result<(), EcsError> {
+        let entities = initialize_entities()?;
+        let query = TypedQuery::<(&Health, &Unregistered)>::new(&entities);
+
+        assert_eq!(
+            query.iter().err(),
+            Some(EcsError::ComponentNotRegistered {
+                type_name: Unregistered::TYPE_NAME
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn typed_query_with_optional_component() -> Result<(), EcsError> {
+        let entities = initialize_entities()?;
+        let query = TypedQuery::<(&Health, Option<&Speed>, Option<&Unregistered>)>::new(&entities);
+
+        let results: Vec<(u32, Option<u32>, bool)> = query
+            .iter()?
+            .map(|item| {
+                item.map(|(health, speed, unregistered)| {
+                    (health.0, speed.map(|speed| speed.0), unregistered.is_some())
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        assert_eq!(
+            results,
+            vec![
+                (100, Some(15), false),
+                (40, None, false),
+                (10, Some(50), false)
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn typed_query_filters() -> Result<(), EcsError> {
+        let entities = initialize_entities()?;
+
+        let without_speed: Vec<u32> = TypedQuery::<&Health>::new(&entities)
+            .without::<Speed>()
+            .iter()?
+            .map(|item| item.map(|health| health.0))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(without_speed, vec![40]);
+
+        let any_of: Vec<usize> = TypedQuery::<Entity>::new(&entities)
+            .any_of::<Speed>()
+            .any_of::<Unregistered>()
+            .iter()?
+            .map(|item| item.map(|entity| entity.index))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(any_of, vec![0, 2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn typed_query_change_filters() -> Result<(), EcsError> {
+        let mut entities = initialize_entities()?;
+        entities.clear_trackers();
+        for item in TypedQuery::<(&mut Health, &Speed)>::new(&entities).iter()? {
+            let (mut health, _) = item?;
+            health.0 += 1;
+        }
+
+        let changed: Vec<u32> = TypedQuery::<&Health>::new(&entities)
+            .changed::<Health>()
+            .iter()?
+            .map(|item| item.map(|health| health.0))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(changed, vec![101, 11]);
+        assert_eq!(
+            TypedQuery::<&Health>::new(&entities)
+                .added::<Health>()
+                .iter()?
+                .count(),
+            0
+        );
+        assert!(TypedQuery::<&Health>::new(&entities)
+            .changed::<Unregistered>()
+            .iter()
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn typed_query_rejects_aliased_access() -> Result<(), EcsError> {
+        let entities = initialize_entities()?;
+
+        let aliased = TypedQuery::<(&mut Health, &Health)>::new(&entities);
+        assert_eq!(
+            aliased.iter().err(),
+            Some(EcsError::AliasedQuery {
+                type_name: Health::TYPE_NAME
+            })
+        );
+        let aliased = TypedQuery::<(Option<&mut Speed>, &mut Speed)>::new(&entities);
+        assert!(aliased.iter().is_err());
+        let shared = TypedQuery::<(&Health, Option<&Health>)>::new(&entities);
+        assert_eq!(shared.iter()?.count(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn typed_query_items_fail_on_conflicting_borrows() -> Result<(), EcsError> {
+        let entities = initialize_entities()?;
+        let held = entities.borrow_mut_at::<Speed>(2)?;
+
+        let results: Vec<Result<u32, EcsError>> =
+            TypedQuery::<(&Health, Option<&Speed>)>::new(&entities)
+                .iter()?
+                .map(|item| item.map(|(health, _)| health.0))
+                .collect();
+        assert_eq!(
+            results,
+            vec![
+                Ok(100),
+                Ok(40),
+                Err(EcsError::AlreadyBorrowed {
+                    entity: entities.entity_at(2),
+                    type_name: Speed::TYPE_NAME
+                })
+            ]
+        );
+        drop(held);
+
+        Ok(())
+    }
+
+    #[test]
+    fn typed_query_children_of() -> Result<(), EcsError> {
+        let mut entities = initialize_entities()?;
+        let backpack = entities.spawn((Health(1),))?;
+        for index in 0..3 {
+            let child = entities.entity_at(index);
+            entities.set_parent(child, backpack)?;
+        }
+        let outsider = entities.spawn((Speed(1),))?;
+        entities.set_parent(outsider, backpack)?;
+
+        let fast_children: Vec<u32> = TypedQuery::<&Speed>::new(&entities)
+            .children_of(backpack)
+            .iter()?
+            .map(|item| item.map(|speed| speed.0))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(fast_children, vec![15, 50, 1]);
+
+        entities.remove_parent(outsider);
+        let children_with_health = TypedQuery::<(Entity, &Health)>::new(&entities)
+            .children_of(backpack)
+            .iter()?
+            .count();
+        assert_eq!(children_with_health, 3);
+
+        Ok(())
+    }
+}
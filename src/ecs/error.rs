@@ -0,0 +1,149 @@
+use std::{error::Error, fmt};
+
+use super::Entity;
+
+/// Everything a fallible ECS call can fail with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EcsError {
+    ComponentNotRegistered {
+        type_name: &'static str,
+    },
+    ComponentMissing {
+        entity: Entity,
+        type_name: &'static str,
+    },
+    /// The component is already borrowed in a way that conflicts with this
+    /// borrow, e.g. mutably by a query that is still alive.
+    AlreadyBorrowed {
+        entity: Entity,
+        type_name: &'static str,
+    },
+    /// Every `type_name` of a `SyncWorld` is locked in a way that conflicts
+    /// with this borrow, e.g. by a `write` that is still alive.
+    ComponentsBorrowed {
+        type_name: &'static str,
+    },
+    /// A query borrows the component mutably together with another borrow
+    /// of it, e.g. `(&mut Health, &Health)`.
+    AliasedQuery {
+        type_name: &'static str,
+    },
+    /// `Query::run` can't hand out components stored in a table.
+    UnsupportedStorage {
+        type_name: &'static str,
+    },
+    ResourceMissing {
+        type_name: &'static str,
+    },
+    /// The resource is already borrowed in a way that conflicts with this
+    /// borrow.
+    ResourceBorrowed {
+        type_name: &'static str,
+    },
+    /// The entity was despawned, so the handle no longer refers to it.
+    StaleEntity {
+        entity: Entity,
+    },
+    /// `with_component` was called before `create_entity`.
+    NoEntityBeingCreated,
+    DuplicateBundleComponent {
+        type_name: &'static str,
+    },
+    DuplicateSystem {
+        name: &'static str,
+    },
+    UnknownSystem {
+        name: &'static str,
+    },
+    /// An `after`/`before` constraint asks a system to run before one in an
+    /// earlier stage.
+    OrderingContradictsStage {
+        name: &'static str,
+    },
+    OrderingCycle,
+    /// `set_parent` would make an entity its own ancestor.
+    HierarchyCycle {
+        child: Entity,
+        parent: Entity,
+    },
+    UnknownPrefab {
+        name: String,
+    },
+    /// No world with this id was added to `Worlds`.
+    UnknownWorld {
+        id: usize,
+    },
+    /// A parallel system used a type its `Access` doesn't declare, or wrote
+    /// one it only declared as read.
+    UndeclaredAccess {
+        system: &'static str,
+        type_name: &'static str,
+    },
+    /// A BSP map node doesn't fit inside the node it is inserted into.
+    InvalidNode {
+        reason: &'static str,
+    },
+}
+
+impl fmt::Display for EcsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ComponentNotRegistered { type_name } => {
+                write!(f, "component {} is not registered", type_name)
+            }
+            Self::ComponentMissing { entity, type_name } => {
+                write!(f, "entity {:?} has no {}", entity, type_name)
+            }
+            Self::AlreadyBorrowed { entity, type_name } => {
+                write!(
+                    f,
+                    "{} of entity {:?} is already borrowed",
+                    type_name, entity
+                )
+            }
+            Self::ComponentsBorrowed { type_name } => {
+                write!(f, "components {} are already borrowed", type_name)
+            }
+            Self::AliasedQuery { type_name } => {
+                write!(f, "query borrows {} mutably more than once", type_name)
+            }
+            Self::UnsupportedStorage { type_name } => {
+                write!(f, "{} is stored in a table and can't be shared", type_name)
+            }
+            Self::ResourceMissing { type_name } => write!(f, "no {} resource", type_name),
+            Self::ResourceBorrowed { type_name } => {
+                write!(f, "resource {} is already borrowed", type_name)
+            }
+            Self::StaleEntity { entity } => write!(f, "entity {:?} is stale", entity),
+            Self::NoEntityBeingCreated => write!(f, "create_entity was never called"),
+            Self::DuplicateBundleComponent { type_name } => {
+                write!(f, "bundle contains {} twice", type_name)
+            }
+            Self::DuplicateSystem { name } => write!(f, "duplicate system name {}", name),
+            Self::UnknownSystem { name } => {
+                write!(f, "system ordering references unknown system {}", name)
+            }
+            Self::OrderingContradictsStage { name } => {
+                write!(f, "ordering of system {} contradicts stage order", name)
+            }
+            Self::OrderingCycle => write!(f, "system ordering has a cycle"),
+            Self::HierarchyCycle { child, parent } => write!(
+                f,
+                "entity {:?} can't be a child of its descendant {:?}",
+                child, parent
+            ),
+            Self::UnknownPrefab { name } => write!(f, "no prefab named {}", name),
+            Self::UnknownWorld { id } => write!(f, "no world with id {}", id),
+            Self::UndeclaredAccess { system, type_name } => {
+                write!(
+                    f,
+                    "system {} didn't declare its access to {}",
+                    system, type_name
+                )
+            }
+            Self::InvalidNode { reason } => write!(f, "invalid map node, {}", reason),
+        }
+    }
+}
+
+impl Error for EcsError {}
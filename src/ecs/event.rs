@@ -0,0 +1,136 @@
+use std::{marker::PhantomData, ops::Range};
+
+/// Double-buffered queue of `E`, stored as a `World` resource. Events sent
+/// during one schedule run stay readable through the next one and are
+/// dropped after that.
+#[derive(Debug)]
+pub struct Events<E> {
+    previous: Vec<E>,
+    current: Vec<E>,
+    /// Id of `previous[0]`. Ids count every event ever sent.
+    previous_start: usize,
+}
+
+impl<E> Default for Events<E> {
+    fn default() -> Self {
+        Self {
+            previous: vec![],
+            current: vec![],
+            previous_start: 0,
+        }
+    }
+}
+
+impl<E> Events<E> {
+    pub fn send(&mut self, event: E) {
+        self.current.push(event);
+    }
+
+    /// Drops the older buffer and starts a new one.
+    pub fn update(&mut self) {
+        self.previous_start += self.previous.len();
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn event_count(&self) -> usize {
+        self.previous_start + self.len()
+    }
+
+    /// The event at `position`, counting from the oldest one still stored.
+    pub(super) fn get(&self, position: usize) -> &E {
+        self.previous
+            .get(position)
+            .unwrap_or_else(|| &self.current[position - self.previous.len()])
+    }
+}
+
+/// Per-consumer cursor into `Events<E>`. Every reader sees every event once,
+/// as long as it reads at least once per schedule run.
+#[derive(Debug)]
+pub struct EventReader<E> {
+    last_event_count: usize,
+    marker: PhantomData<fn() -> E>,
+}
+
+impl<E> Default for EventReader<E> {
+    fn default() -> Self {
+        Self {
+            last_event_count: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<E> EventReader<E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Events this reader hasn't seen yet, oldest first.
+    pub fn read<'a>(&mut self, events: &'a Events<E>) -> impl Iterator<Item = &'a E> + use<'a, E> {
+        self.unread(events).map(|position| events.get(position))
+    }
+
+    /// Positions, as taken by `Events::get`, of the events this reader
+    /// hasn't seen yet. Marks them as seen.
+    pub(super) fn unread(&mut self, events: &Events<E>) -> Range<usize> {
+        let skip = self.last_event_count.saturating_sub(events.previous_start);
+        self.last_event_count = events.event_count();
+        skip.min(events.len())..events.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Bumped(pub u32);
+
+    #[test]
+    fn readers_drain_independently() {
+        let mut events = Events::default();
+        let mut log = EventReader::new();
+        let mut sound = EventReader::new();
+        events.send(Bumped(1));
+        events.send(Bumped(2));
+
+        assert_eq!(
+            log.read(&events).collect::<Vec<_>>(),
+            [&Bumped(1), &Bumped(2)]
+        );
+        assert_eq!(log.read(&events).count(), 0);
+
+        events.send(Bumped(3));
+        assert_eq!(log.read(&events).collect::<Vec<_>>(), [&Bumped(3)]);
+        assert_eq!(sound.read(&events).count(), 3);
+    }
+
+    #[test]
+    fn events_live_for_one_update() {
+        let mut events = Events::default();
+        let mut reader = EventReader::new();
+        events.send(Bumped(1));
+        events.update();
+        events.send(Bumped(2));
+        assert_eq!(events.len(), 2);
+        assert_eq!(reader.read(&events).count(), 2);
+
+        events.update();
+        events.update();
+        assert!(events.is_empty());
+        events.send(Bumped(3));
+
+        let mut late = EventReader::new();
+        assert_eq!(late.read(&events).collect::<Vec<_>>(), [&Bumped(3)]);
+        assert_eq!(reader.read(&events).collect::<Vec<_>>(), [&Bumped(3)]);
+    }
+}
@@ -0,0 +1,53 @@
+use std::{any::TypeId, collections::HashMap, fmt, rc::Rc};
+
+use super::{Entity, World};
+
+/// Callback registered with `World::on_add`, `on_replace` or `on_remove`.
+/// It can capture state, e.g. a spatial index it keeps in sync.
+pub type Hook = Rc<dyn Fn(&mut World, Entity)>;
+
+/// What happened to a component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HookKind {
+    /// Inserted on an entity that didn't have it.
+    Add,
+    /// Inserted on an entity that already had it; the new value is in place.
+    Replace,
+    /// About to be removed, or its entity about to be despawned; the value
+    /// is still in place.
+    Remove,
+}
+
+/// A change to a component type that has hooks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HookEvent {
+    pub kind: HookKind,
+    pub type_id: TypeId,
+    pub entity: Entity,
+}
+
+#[derive(Default, Clone)]
+pub(super) struct Hooks {
+    hooks: HashMap<(TypeId, HookKind), Vec<Hook>>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.hooks.iter().map(|(key, hooks)| (key, hooks.len())))
+            .finish()
+    }
+}
+
+impl Hooks {
+    pub fn add(&mut self, type_id: TypeId, kind: HookKind, hook: Hook) {
+        self.hooks.entry((type_id, kind)).or_default().push(hook);
+    }
+
+    pub fn get(&self, event: &HookEvent) -> Vec<Hook> {
+        self.hooks
+            .get(&(event.type_id, event.kind))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
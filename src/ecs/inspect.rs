@@ -0,0 +1,163 @@
+//! Text dump of everything in a `World`, for seeing what's going on when a
+//! bug shows up:
+//!
+//! ```text
+//! resource Map
+//!   21x80
+//! entity 0 0
+//! component Position
+//!   Position { x: 19, y: 69 }
+//! component Player
+//! ```
+//!
+//! Every live entity and resource is listed with its type names: a
+//! component's `Component::TYPE_NAME`, a resource's full path. Values are
+//! only shown for types registered with `World::register_described`.
+
+use std::{
+    any::{Any, TypeId},
+    cell::Ref,
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use super::entity::Entities;
+use super::persist::write_value;
+use super::resource::Resources;
+
+/// Opt-in for components and resources whose value `World::dump` shows.
+pub trait Describe: 'static {
+    /// Human-readable value. It may span several lines.
+    fn describe(&self) -> String;
+}
+
+type DescribeFn = fn(&dyn Any) -> String;
+
+fn describe<T: Describe>(value: &dyn Any) -> String {
+    value.downcast_ref::<T>().unwrap().describe()
+}
+
+#[derive(Debug, Default, Clone)]
+pub(super) struct Describers {
+    describers: HashMap<TypeId, DescribeFn>,
+}
+
+impl Describers {
+    pub fn add<T: Describe>(&mut self) {
+        self.describers.insert(TypeId::of::<T>(), describe::<T>);
+    }
+
+    /// Describes `type_id` values like `from` does, unless they are
+    /// described already.
+    pub fn copy(&mut self, from: &Describers, type_id: TypeId) {
+        if let Some(describe) = from.describers.get(&type_id) {
+            self.describers.entry(type_id).or_insert(*describe);
+        }
+    }
+
+    pub fn dump(
+        &self,
+        entities: &Entities,
+        resources: &Resources,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        for (type_id, name, resource) in resources.entries() {
+            writeln!(out, "resource {}", name)?;
+            let value = resource
+                .try_borrow()
+                .ok()
+                .map(|resource| Ref::map(resource, |resource| &**resource));
+            self.write_value(out, type_id, value)?;
+        }
+        for (entity, alive) in entities.slots() {
+            if !alive {
+                continue;
+            }
+            writeln!(out, "entity {} {}", entity.index, entity.generation)?;
+            for (type_id, name, component) in entities.components_of(entity) {
+                writeln!(out, "component {}", name)?;
+                self.write_value(out, type_id, component.try_borrow().ok())?;
+            }
+            if let Some(parent) = entities.parent(entity) {
+                writeln!(out, "parent {} {}", parent.index, parent.generation)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the description of `value`, or a marker if it couldn't be
+    /// borrowed.
+    fn write_value(
+        &self,
+        out: &mut impl Write,
+        type_id: TypeId,
+        value: Option<Ref<'_, dyn Any>>,
+    ) -> io::Result<()> {
+        let Some(describe) = self.describers.get(&type_id) else {
+            return Ok(());
+        };
+        match value {
+            Some(value) => write_value(out, &describe(&*value)),
+            None => write_value(out, "<mutably borrowed>"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ecs::{Component, World};
+
+    use super::*;
+
+    #[derive(Component)]
+    struct Health(pub u32);
+    #[derive(Component)]
+    struct Hidden;
+    struct Turn(pub u32);
+
+    impl Describe for Health {
+        fn describe(&self) -> String {
+            format!("{} hp", self.0)
+        }
+    }
+
+    impl Describe for Turn {
+        fn describe(&self) -> String {
+            format!("turn\n{}", self.0)
+        }
+    }
+
+    fn dump(world: &World) -> String {
+        let mut out = vec![];
+        world.dump(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn dump_lists_everything() {
+        let mut world = World::new();
+        world.register_described::<Health>();
+        world.register_described::<Turn>();
+        world.add_resource(Turn(3));
+        world.add_resource(Hidden);
+        let dead = world.spawn((Hidden,)).unwrap();
+        let player = world.spawn((Health(10), Hidden)).unwrap();
+        let potion = world.spawn((Health(1),)).unwrap();
+        world.set_parent(potion, player).unwrap();
+        world.despawn(dead).unwrap();
+
+        let expected = "resource Hidden\n\
+             resource Turn\n  turn\n  3\n\
+             entity 1 0\n\
+             component Hidden\n\
+             component Health\n  10 hp\n\
+             entity 2 0\n\
+             component Health\n  1 hp\n\
+             parent 1 0\n";
+        assert_eq!(dump(&world), expected);
+
+        let health = world.entity(potion).unwrap().get_mut::<Health>().unwrap();
+        assert!(dump(&world).ends_with("  <mutably borrowed>\nparent 1 0\n"));
+        drop(health);
+    }
+}
@@ -1,5 +1,38 @@
+mod command;
+pub mod component;
 mod entity;
+mod error;
+mod event;
+mod hook;
+pub mod inspect;
+mod parallel;
+pub mod persist;
+pub mod prefab;
 mod resource;
+mod schedule;
+mod snapshot;
+mod sync_world;
 mod world;
+mod worlds;
 
+pub use command::{Commands, SpawnCommands};
+pub use component::{Component, Resource};
+pub use concoeur_derive::{Component, Resource};
+pub use entity::bundle::Bundle;
+pub use entity::entity_ref::{EntityMut, EntityRef};
+pub use entity::storage::StorageKind;
+pub use entity::table::{Table, TableColumn};
+pub use entity::transfer::{EntityMap, MapEntities};
+pub use entity::typed_query::{QueryAccess, QueryData, TypedQuery};
+pub use entity::Entity;
+pub use error::EcsError;
+pub use event::{EventReader, Events};
+pub use hook::{Hook, HookEvent, HookKind};
+pub use inspect::Describe;
+pub use parallel::{Access, ParallelSchedule, ParallelSystem};
+pub use persist::{Persist, SaveError};
+pub use schedule::{Schedule, Stage, System, SystemEntry};
+pub use snapshot::Snapshot;
+pub use sync_world::{ReadComponents, ReadResource, SyncWorld, WriteComponents, WriteResource};
 pub use world::*;
+pub use worlds::Worlds;
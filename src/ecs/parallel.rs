@@ -0,0 +1,438 @@
+use std::{
+    any::{Any, TypeId},
+    fmt, panic,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Barrier, Mutex,
+    },
+    thread,
+};
+
+use super::sync_world::{self, SyncWorld};
+
+/// Component and resource types a parallel system reads and writes. Two
+/// systems conflict when one writes a type the other reads or writes.
+#[derive(Debug, Default, Clone)]
+pub struct Access {
+    reads: Vec<TypeId>,
+    writes: Vec<TypeId>,
+}
+
+impl Access {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read<T: Any>(mut self) -> Self {
+        self.reads.push(TypeId::of::<T>());
+        self
+    }
+
+    pub fn write<T: Any>(mut self) -> Self {
+        self.writes.push(TypeId::of::<T>());
+        self
+    }
+
+    pub fn conflicts_with(&self, other: &Access) -> bool {
+        let writes_other = self
+            .writes
+            .iter()
+            .any(|type_id| other.reads.contains(type_id) || other.writes.contains(type_id));
+        let other_writes = other
+            .writes
+            .iter()
+            .any(|type_id| self.reads.contains(type_id));
+        writes_other || other_writes
+    }
+
+    /// Declared writes allow reads too.
+    pub(crate) fn allows_read(&self, type_id: &TypeId) -> bool {
+        self.reads.contains(type_id) || self.writes.contains(type_id)
+    }
+
+    pub(crate) fn allows_write(&self, type_id: &TypeId) -> bool {
+        self.writes.contains(type_id)
+    }
+}
+
+/// A system run by `ParallelSchedule`. The `SyncWorld` accessors return
+/// `EcsError::UndeclaredAccess` for any type its `Access` doesn't list.
+pub trait ParallelSystem: Send {
+    fn run(&mut self, world: &SyncWorld);
+}
+
+impl<F: FnMut(&SyncWorld) + Send> ParallelSystem for F {
+    fn run(&mut self, world: &SyncWorld) {
+        self(world)
+    }
+}
+
+struct ParallelEntry {
+    name: &'static str,
+    access: Access,
+    system: Box<dyn ParallelSystem>,
+}
+
+impl ParallelEntry {
+    fn run(&mut self, world: &SyncWorld) {
+        sync_world::with_access(self.name, &self.access, || self.system.run(world));
+    }
+}
+
+/// Runs systems on a `SyncWorld`, several at a time. Systems are split into
+/// batches of mutually non-conflicting systems; a system always runs after
+/// every earlier-added system it conflicts with, so the result is the same
+/// as running them one by one in insertion order.
+///
+/// Each `run` starts its worker threads once and reuses them for every
+/// batch; they can't outlive the run since they borrow the world.
+pub struct ParallelSchedule {
+    systems: Vec<ParallelEntry>,
+    threads: usize,
+}
+
+impl Default for ParallelSchedule {
+    fn default() -> Self {
+        let threads = thread::available_parallelism()
+            .map(|threads| threads.get())
+            .unwrap_or(1);
+        Self::with_threads(threads)
+    }
+}
+
+impl ParallelSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_threads(threads: usize) -> Self {
+        Self {
+            systems: vec![],
+            threads: threads.max(1),
+        }
+    }
+
+    pub fn add_system(
+        &mut self,
+        name: &'static str,
+        access: Access,
+        system: impl ParallelSystem + 'static,
+    ) -> &mut Self {
+        self.systems.push(ParallelEntry {
+            name,
+            access,
+            system: Box::new(system),
+        });
+        self
+    }
+
+    /// Names of the systems in each batch, in the order the batches run.
+    pub fn batches(&self) -> Vec<Vec<&'static str>> {
+        self.batch_indexes()
+            .iter()
+            .map(|batch| {
+                batch
+                    .iter()
+                    .map(|index| self.systems[*index].name)
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn batch_indexes(&self) -> Vec<Vec<usize>> {
+        let mut batch_of: Vec<usize> = vec![];
+        let mut batches: Vec<Vec<usize>> = vec![];
+        for (index, entry) in self.systems.iter().enumerate() {
+            let batch = (0..index)
+                .filter(|earlier| self.systems[*earlier].access.conflicts_with(&entry.access))
+                .map(|earlier| batch_of[earlier] + 1)
+                .max()
+                .unwrap_or(0);
+            batch_of.push(batch);
+            if batch == batches.len() {
+                batches.push(vec![]);
+            }
+            batches[batch].push(index);
+        }
+        batches
+    }
+
+    pub fn run(&mut self, world: &SyncWorld) {
+        let batches = self.batch_indexes();
+        let widest = batches.iter().map(Vec::len).max().unwrap_or(0);
+        if widest <= 1 || self.threads == 1 {
+            self.systems.iter_mut().for_each(|entry| entry.run(world));
+            return;
+        }
+
+        // Each thread claims the next unclaimed system of the batch until
+        // there are none left, then waits for the others at the barrier.
+        // Nobody claims the same system twice, so the locks never block.
+        let systems: Vec<Mutex<&mut ParallelEntry>> =
+            self.systems.iter_mut().map(Mutex::new).collect();
+        let next: Vec<AtomicUsize> = batches.iter().map(|_| AtomicUsize::new(0)).collect();
+        let threads = self.threads.min(widest);
+        let barrier = Barrier::new(threads);
+        let panicked = AtomicBool::new(false);
+        let payload = Mutex::new(None);
+        let work = || {
+            for (batch, next) in batches.iter().zip(&next) {
+                loop {
+                    let claimed = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(index) = batch.get(claimed) else {
+                        break;
+                    };
+                    let mut entry = systems[*index].lock().unwrap();
+                    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| entry.run(world)));
+                    if let Err(error) = result {
+                        panicked.store(true, Ordering::Relaxed);
+                        payload.lock().unwrap().get_or_insert(error);
+                    }
+                }
+                barrier.wait();
+                if panicked.load(Ordering::Relaxed) {
+                    break;
+                }
+            }
+        };
+        thread::scope(|scope| {
+            for _ in 1..threads {
+                scope.spawn(work);
+            }
+            work();
+        });
+        if let Some(error) = payload.into_inner().unwrap() {
+            panic::resume_unwind(error);
+        }
+    }
+}
+
+impl fmt::Debug for ParallelSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParallelSchedule")
+            .field("batches", &self.batches())
+            .field("threads", &self.threads)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashSet,
+        sync::{Arc, Barrier, Mutex},
+    };
+
+    use super::*;
+    use crate::ecs::{Component, EcsError};
+
+    #[derive(Component)]
+    struct Position(pub i32);
+    #[derive(Component)]
+    struct Velocity(pub i32);
+    #[derive(Component)]
+    struct Health(pub u32);
+    #[derive(Component)]
+    struct Regen(pub u32);
+
+    fn initialize_world() -> Result<SyncWorld, EcsError> {
+        let mut world = SyncWorld::new();
+        world.register_component::<Position>();
+        world.register_component::<Velocity>();
+        world.register_component::<Health>();
+        world.register_component::<Regen>();
+        for index in 0..100 {
+            world
+                .create_entity()
+                .with_component(Position(0))?
+                .with_component(Velocity(index))?
+                .with_component(Health(0))?
+                .with_component(Regen(2))?;
+        }
+        Ok(world)
+    }
+
+    fn movement(world: &SyncWorld) {
+        let velocities = world.read::<Velocity>().unwrap();
+        let mut positions = world.write::<Position>().unwrap();
+        for (entity, velocity) in velocities.iter() {
+            positions.get_mut(entity).unwrap().0 += velocity.0;
+        }
+    }
+
+    fn regen(world: &SyncWorld) {
+        let regens = world.read::<Regen>().unwrap();
+        let mut healths = world.write::<Health>().unwrap();
+        for (entity, regen) in regens.iter() {
+            healths.get_mut(entity).unwrap().0 += regen.0;
+        }
+    }
+
+    #[test]
+    fn conflicting_access() {
+        let movement = Access::new().read::<Velocity>().write::<Position>();
+        let render = Access::new().read::<Position>();
+        let regen = Access::new().read::<Regen>().write::<Health>();
+        let also_reads_velocity = Access::new().read::<Velocity>();
+
+        assert!(movement.conflicts_with(&render));
+        assert!(render.conflicts_with(&movement));
+        assert!(!movement.conflicts_with(&regen));
+        assert!(!movement.conflicts_with(&also_reads_velocity));
+    }
+
+    #[test]
+    fn systems_are_batched_by_access() {
+        let mut schedule = ParallelSchedule::with_threads(4);
+        schedule
+            .add_system(
+                "movement",
+                Access::new().read::<Velocity>().write::<Position>(),
+                movement,
+            )
+            .add_system(
+                "regen",
+                Access::new().read::<Regen>().write::<Health>(),
+                regen,
+            )
+            .add_system(
+                "render",
+                Access::new().read::<Position>().read::<Health>(),
+                |_: &SyncWorld| {},
+            )
+            .add_system(
+                "slow",
+                Access::new().write::<Velocity>(),
+                |_: &SyncWorld| {},
+            );
+
+        assert_eq!(
+            schedule.batches(),
+            vec![vec!["movement", "regen"], vec!["render", "slow"]]
+        );
+    }
+
+    #[test]
+    fn non_conflicting_systems_run_concurrently() -> Result<(), EcsError> {
+        let world = initialize_world()?;
+        // Both systems wait for each other, so this only finishes if they run
+        // at the same time.
+        let barrier = Arc::new(Barrier::new(2));
+        let mut schedule = ParallelSchedule::with_threads(2);
+        for name in ["movement", "regen"] {
+            let barrier = barrier.clone();
+            let access = if name == "movement" {
+                Access::new().read::<Velocity>().write::<Position>()
+            } else {
+                Access::new().read::<Regen>().write::<Health>()
+            };
+            schedule.add_system(name, access, move |world: &SyncWorld| {
+                barrier.wait();
+                if name == "movement" {
+                    movement(world)
+                } else {
+                    regen(world)
+                }
+            });
+        }
+
+        schedule.run(&world);
+        schedule.run(&world);
+
+        let positions = world.read::<Position>().unwrap();
+        let healths = world.read::<Health>().unwrap();
+        for (entity, position) in positions.iter() {
+            assert_eq!(position.0, entity.index as i32 * 2);
+            assert_eq!(healths.get(entity).unwrap().0, 4);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn worker_threads_are_reused() -> Result<(), EcsError> {
+        let world = initialize_world()?;
+        let threads = Arc::new(Mutex::new(HashSet::new()));
+        let barrier = Arc::new(Barrier::new(2));
+        let mut schedule = ParallelSchedule::with_threads(2);
+        // Three batches of two systems that wait for each other, so every
+        // batch runs on both threads.
+        for _ in 0..3 {
+            for access in [
+                Access::new().write::<Position>(),
+                Access::new().write::<Health>(),
+            ] {
+                let threads = threads.clone();
+                let barrier = barrier.clone();
+                schedule.add_system("system", access, move |_: &SyncWorld| {
+                    barrier.wait();
+                    threads.lock().unwrap().insert(thread::current().id());
+                });
+            }
+        }
+
+        schedule.run(&world);
+
+        // This thread and the one worker, for all three batches.
+        assert_eq!(schedule.batches().len(), 3);
+        assert_eq!(threads.lock().unwrap().len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "regen failed")]
+    fn worker_panics_reach_the_caller() {
+        let world = SyncWorld::new();
+        let mut schedule = ParallelSchedule::with_threads(2);
+        schedule
+            .add_system("movement", Access::new(), |_: &SyncWorld| {})
+            .add_system("regen", Access::new(), |_: &SyncWorld| {
+                panic!("regen failed")
+            });
+
+        schedule.run(&world);
+    }
+
+    #[test]
+    fn undeclared_access_is_an_error() -> Result<(), EcsError> {
+        let mut world = initialize_world()?;
+        world.add_resource(0u32);
+        let results = Arc::new(Mutex::new(vec![]));
+        let mut schedule = ParallelSchedule::with_threads(2);
+        let sink = results.clone();
+        schedule.add_system(
+            "reader",
+            Access::new().read::<Position>().write::<Health>(),
+            move |world: &SyncWorld| {
+                let mut results = sink.lock().unwrap();
+                results.push(world.read::<Position>().err());
+                results.push(world.read::<Health>().err());
+                results.push(world.write::<Position>().err());
+                results.push(world.read::<Velocity>().err());
+                results.push(world.resource::<u32>().err());
+            },
+        );
+
+        schedule.run(&world);
+
+        let undeclared = |type_name| {
+            Some(EcsError::UndeclaredAccess {
+                system: "reader",
+                type_name,
+            })
+        };
+        assert_eq!(
+            *results.lock().unwrap(),
+            vec![
+                None,
+                None,
+                undeclared(Position::TYPE_NAME),
+                undeclared(Velocity::TYPE_NAME),
+                undeclared("u32"),
+            ]
+        );
+        // Outside of the schedule everything is allowed again.
+        assert!(world.write::<Position>().is_ok());
+        Ok(())
+    }
+}
@@ -0,0 +1,811 @@
+//! Saving a `World` to, and loading it from, a versioned text format:
+//!
+//! ```text
+//! concoeur-save 1
+//! spawned 4
+//! resource Map
+//!   ##########
+//! entity 0 0 0
+//! component Position
+//!   19 69
+//! component Player
+//! dead 1 3
+//! entity 2 0 3
+//! parent 0 0
+//! free 1
+//! ```
+//!
+//! Every entity slot is written in index order, dead ones included, so
+//! handles (and stale handles) mean the same thing after loading. `free`
+//! lists the dead slots in the order new entities reuse them, so spawning
+//! after a load hands out the same handles. Live entities carry their
+//! spawn sequence and `spawned` is the number of entities ever created, so
+//! queries keep their order. A value is the
+//! lines indented by two spaces below its `resource` or `component` line.
+//! An entity's `parent` line comes after its components.
+//! Only types registered with `register_saved_component` or
+//! `register_saved_resource` are written; everything else is skipped.
+
+use std::{
+    any::TypeId,
+    error::Error,
+    fmt,
+    io::{self, BufRead, Write},
+};
+
+use super::{
+    resource::{short_type_name, Resources},
+    Component, EcsError, Entity, World,
+};
+
+const HEADER: &str = "concoeur-save";
+const VERSION: u32 = 1;
+const INDENT: &str = "  ";
+
+/// Opt-in for components and resources that can be saved.
+pub trait Persist: Sized + 'static {
+    /// Name written to the save file. It has to be unique among persisted
+    /// types, or registering the second one fails, and should not change,
+    /// or older saves won't load.
+    const NAME: &'static str;
+
+    fn save(&self) -> String;
+
+    fn load(value: &str) -> Result<Self, String>;
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    UnsupportedVersion {
+        found: String,
+    },
+    /// `World::load` only loads into a world without entities.
+    WorldNotEmpty,
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// A value couldn't be read from the world, e.g. because it was
+    /// borrowed mutably while saving.
+    Ecs(EcsError),
+    /// Two saved components, or two saved resources, have the same
+    /// `Persist::NAME`.
+    DuplicateName {
+        name: &'static str,
+        registered: &'static str,
+        type_name: &'static str,
+    },
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::UnsupportedVersion { found } => {
+                write!(f, "unsupported save version {:?}", found)
+            }
+            Self::WorldNotEmpty => write!(f, "can only load into a world without entities"),
+            Self::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            Self::Ecs(err) => write!(f, "{}", err),
+            Self::DuplicateName {
+                name,
+                registered,
+                type_name,
+            } => write!(
+                f,
+                "{} and {} are both saved as {:?}",
+                registered, type_name, name
+            ),
+        }
+    }
+}
+
+impl Error for SaveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Ecs(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<EcsError> for SaveError {
+    fn from(err: EcsError) -> Self {
+        Self::Ecs(err)
+    }
+}
+
+/// Why a component value couldn't be put into an entity.
+#[derive(Debug)]
+pub(super) enum LoadError {
+    /// The value doesn't parse.
+    Invalid(String),
+    Ecs(EcsError),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct ComponentPersister {
+    pub type_id: TypeId,
+    type_name: &'static str,
+    name: &'static str,
+    save: fn(&World, Entity) -> Result<Option<String>, EcsError>,
+    pub load: fn(&mut World, Entity, &str) -> Result<(), LoadError>,
+    /// Parses a value without inserting it.
+    pub check: fn(&str) -> Result<(), String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ResourcePersister {
+    type_id: TypeId,
+    type_name: &'static str,
+    name: &'static str,
+    save: fn(&World) -> Result<Option<String>, EcsError>,
+    load: fn(&mut Resources, &str) -> Result<(), String>,
+}
+
+/// `None` if `entity` has no `T`.
+fn save_component<T: Persist + Component>(
+    world: &World,
+    entity: Entity,
+) -> Result<Option<String>, EcsError> {
+    match world.entity(entity)?.get::<T>() {
+        Ok(component) => Ok(Some(component.save())),
+        Err(EcsError::ComponentMissing { .. } | EcsError::ComponentNotRegistered { .. }) => {
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn load_component<T: Persist + Component>(
+    world: &mut World,
+    entity: Entity,
+    value: &str,
+) -> Result<(), LoadError> {
+    let component = T::load(value).map_err(LoadError::Invalid)?;
+    world
+        .insert_unhooked(entity, component)
+        .map_err(LoadError::Ecs)
+}
+
+fn check_component<T: Persist>(value: &str) -> Result<(), String> {
+    T::load(value).map(|_| ())
+}
+
+fn save_resource<T: Persist>(world: &World) -> Result<Option<String>, EcsError> {
+    match world.resource::<T>() {
+        Ok(resource) => Ok(Some(resource.save())),
+        Err(EcsError::ResourceMissing { .. }) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn load_resource<T: Persist>(resources: &mut Resources, value: &str) -> Result<(), String> {
+    resources.add(T::load(value)?);
+    Ok(())
+}
+
+/// Which components and resources a `World` saves, in registration order.
+#[derive(Debug, Clone, Default)]
+pub struct Persisters {
+    components: Vec<ComponentPersister>,
+    resources: Vec<ResourcePersister>,
+}
+
+impl Persisters {
+    /// Fails if another saved component type has the same `NAME`, which
+    /// would leave one of them out of saves.
+    pub fn add_component<T: Persist + Component>(&mut self) -> Result<(), SaveError> {
+        let type_id = TypeId::of::<T>();
+        match self.components.iter().find(|entry| entry.name == T::NAME) {
+            Some(entry) if entry.type_id == type_id => {}
+            Some(entry) => {
+                return Err(SaveError::DuplicateName {
+                    name: T::NAME,
+                    registered: entry.type_name,
+                    type_name: T::TYPE_NAME,
+                })
+            }
+            None => self.components.push(ComponentPersister {
+                type_id,
+                type_name: T::TYPE_NAME,
+                name: T::NAME,
+                save: save_component::<T>,
+                load: load_component::<T>,
+                check: check_component::<T>,
+            }),
+        }
+        Ok(())
+    }
+
+    /// Fails if another saved resource type has the same `NAME`.
+    pub fn add_resource<T: Persist>(&mut self) -> Result<(), SaveError> {
+        let type_id = TypeId::of::<T>();
+        match self.resources.iter().find(|entry| entry.name == T::NAME) {
+            Some(entry) if entry.type_id == type_id => {}
+            Some(entry) => {
+                return Err(SaveError::DuplicateName {
+                    name: T::NAME,
+                    registered: entry.type_name,
+                    type_name: short_type_name::<T>(),
+                })
+            }
+            None => self.resources.push(ResourcePersister {
+                type_id,
+                type_name: short_type_name::<T>(),
+                name: T::NAME,
+                save: save_resource::<T>,
+                load: load_resource::<T>,
+            }),
+        }
+        Ok(())
+    }
+
+    /// Saves the `type_id` components like `from` does, unless they are
+    /// saved already.
+    pub fn copy_component(&mut self, from: &Persisters, type_id: TypeId) {
+        let Some(persister) = from
+            .components
+            .iter()
+            .find(|entry| entry.type_id == type_id)
+        else {
+            return;
+        };
+        let missing = self
+            .components
+            .iter()
+            .all(|entry| entry.type_id != type_id && entry.name != persister.name);
+        if missing {
+            self.components.push(*persister);
+        }
+    }
+
+    pub fn save(&self, world: &World, out: &mut impl Write) -> Result<(), SaveError> {
+        writeln!(out, "{} {}", HEADER, VERSION)?;
+        writeln!(out, "spawned {}", world.spawn_count())?;
+        for resource in &self.resources {
+            if let Some(value) = (resource.save)(world)? {
+                writeln!(out, "resource {}", resource.name)?;
+                write_value(out, &value)?;
+            }
+        }
+        for (entity, _) in world.slots() {
+            let Some(sequence) = world.spawn_sequence(entity) else {
+                writeln!(out, "dead {} {}", entity.index, entity.generation)?;
+                continue;
+            };
+            writeln!(
+                out,
+                "entity {} {} {}",
+                entity.index, entity.generation, sequence
+            )?;
+            for component in &self.components {
+                if let Some(value) = (component.save)(world, entity)? {
+                    writeln!(out, "component {}", component.name)?;
+                    write_value(out, &value)?;
+                }
+            }
+            if let Some(parent) = world.parent(entity) {
+                writeln!(out, "parent {} {}", parent.index, parent.generation)?;
+            }
+        }
+        let free: Vec<String> = world.free_slots().map(|index| index.to_string()).collect();
+        if !free.is_empty() {
+            writeln!(out, "free {}", free.join(" "))?;
+        }
+        Ok(())
+    }
+
+    /// Loads the entities in `input` into `world`, which has none yet, and
+    /// returns the resources for `World::load` to add once it succeeds.
+    pub fn load(&self, world: &mut World, input: impl BufRead) -> Result<Resources, SaveError> {
+        let mut lines = input.lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        if header != format!("{} {}", HEADER, VERSION) {
+            return Err(SaveError::UnsupportedVersion { found: header });
+        }
+
+        let mut entity = None;
+        // Parents can come later in the file than their children, so they
+        // are set once every slot exists.
+        let mut parents = vec![];
+        let mut resources = Resources::default();
+        let mut spawned = None;
+        let mut free = None;
+        // Lowest spawn count that is above every loaded entity's sequence.
+        let mut min_spawned = 0;
+        // The header is line 1.
+        for record in read_records(lines, 2)? {
+            match record.words().as_slice() {
+                ["spawned", count] => {
+                    record.check_no_value()?;
+                    let Ok(count) = count.parse::<u64>() else {
+                        return Err(record.error("invalid spawn count"));
+                    };
+                    spawned = Some((record.line, count));
+                }
+                [kind @ ("entity" | "dead"), index, generation, sequence @ ..] => {
+                    record.check_no_value()?;
+                    let (Ok(index), Ok(generation)) = (index.parse(), generation.parse()) else {
+                        return Err(record.error("invalid entity handle"));
+                    };
+                    let sequence = match (*kind, sequence) {
+                        ("entity", [sequence]) => {
+                            let Ok(sequence) = sequence.parse::<u64>() else {
+                                return Err(record.error("invalid spawn sequence"));
+                            };
+                            min_spawned = min_spawned.max(sequence + 1);
+                            Some(sequence)
+                        }
+                        ("dead", []) => None,
+                        _ => return Err(record.error("unrecognized line")),
+                    };
+                    let pushed = world.push_slot(generation, sequence);
+                    if pushed.index != index {
+                        return Err(record.error("entity slots are out of order"));
+                    }
+                    entity = sequence.map(|_| pushed);
+                }
+                ["component", name] => {
+                    let Some(entity) = entity else {
+                        return Err(record.error("component without a live entity"));
+                    };
+                    let component = self
+                        .component(name)
+                        .ok_or_else(|| record.error(&format!("unknown component {}", name)))?;
+                    (component.load)(world, entity, &record.value()).map_err(|err| match err {
+                        LoadError::Invalid(message) => record.error(&message),
+                        LoadError::Ecs(err) => record.error(&err.to_string()),
+                    })?;
+                }
+                ["free", indexes @ ..] => {
+                    record.check_no_value()?;
+                    let Ok(indexes) = indexes
+                        .iter()
+                        .map(|index| index.parse::<usize>())
+                        .collect::<Result<Vec<_>, _>>()
+                    else {
+                        return Err(record.error("invalid free slot"));
+                    };
+                    free = Some((record.line, indexes));
+                }
+                ["parent", index, generation] => {
+                    record.check_no_value()?;
+                    let Some(entity) = entity else {
+                        return Err(record.error("parent without a live entity"));
+                    };
+                    let (Ok(index), Ok(generation)) = (index.parse(), generation.parse()) else {
+                        return Err(record.error("invalid entity handle"));
+                    };
+                    parents.push((record.line, entity, Entity { index, generation }));
+                }
+                ["resource", name] => {
+                    let resource = self
+                        .resources
+                        .iter()
+                        .find(|entry| entry.name == *name)
+                        .ok_or_else(|| record.error(&format!("unknown resource {}", name)))?;
+                    (resource.load)(&mut resources, &record.value())
+                        .map_err(|message| record.error(&message))?;
+                }
+                _ => return Err(record.error("unrecognized line")),
+            }
+        }
+        for (line, child, parent) in parents {
+            world
+                .set_parent(child, parent)
+                .map_err(|error| SaveError::Parse {
+                    line,
+                    message: error.to_string(),
+                })?;
+        }
+        if let Some((line, indexes)) = free {
+            if !world.set_free_slots(&indexes) {
+                return Err(SaveError::Parse {
+                    line,
+                    message: "free slots don't match the dead entities".to_string(),
+                });
+            }
+        }
+        match spawned {
+            Some((line, count)) if count < min_spawned => {
+                return Err(SaveError::Parse {
+                    line,
+                    message: "spawn count is below a saved spawn sequence".to_string(),
+                })
+            }
+            Some((_, count)) => world.set_spawn_count(count),
+            None => world.set_spawn_count(min_spawned),
+        }
+        Ok(resources)
+    }
+
+    pub(super) fn component(&self, name: &str) -> Option<&ComponentPersister> {
+        self.components.iter().find(|entry| entry.name == name)
+    }
+}
+
+/// A line of words plus the value indented below it.
+pub(super) struct Record {
+    pub line: usize,
+    pub header: String,
+    lines: Vec<String>,
+}
+
+impl Record {
+    pub fn value(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn words(&self) -> Vec<&str> {
+        self.header.split(' ').collect()
+    }
+
+    /// Fails if something is indented below a line that takes no value.
+    pub fn check_no_value(&self) -> Result<(), SaveError> {
+        if self.lines.is_empty() {
+            Ok(())
+        } else {
+            Err(SaveError::Parse {
+                line: self.line + 1,
+                message: "value without a component or resource".to_string(),
+            })
+        }
+    }
+
+    pub fn error(&self, message: &str) -> SaveError {
+        SaveError::Parse {
+            line: self.line,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Splits lines into records. Blank lines and lines starting with `#` are
+/// skipped; `first_line` is the line number of the first line in `lines`.
+pub(super) fn read_records(
+    lines: impl Iterator<Item = io::Result<String>>,
+    first_line: usize,
+) -> Result<Vec<Record>, SaveError> {
+    let mut records: Vec<Record> = vec![];
+    let mut in_value = false;
+    for (line_index, line) in lines.enumerate() {
+        let line = line?;
+        let line_number = first_line + line_index;
+        if let Some(value_line) = line.strip_prefix(INDENT) {
+            let Some(record) = records.last_mut().filter(|_| in_value) else {
+                return Err(SaveError::Parse {
+                    line: line_number,
+                    message: "value without a component or resource".to_string(),
+                });
+            };
+            record.lines.push(value_line.to_string());
+            continue;
+        }
+        in_value = false;
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        records.push(Record {
+            line: line_number,
+            header: line,
+            lines: vec![],
+        });
+        in_value = true;
+    }
+    Ok(records)
+}
+
+/// Writes `value` indented below the line it belongs to.
+pub(super) fn write_value(out: &mut impl Write, value: &str) -> io::Result<()> {
+    if value.is_empty() {
+        return Ok(());
+    }
+    for line in value.split('\n') {
+        writeln!(out, "{}{}", INDENT, line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ecs::{Component, EcsError};
+
+    #[derive(Debug, PartialEq, Component)]
+    struct Health(pub u32);
+    #[derive(Debug, PartialEq)]
+    struct Turn(pub u32);
+    #[derive(Component)]
+    struct Unsaved;
+
+    impl Persist for Health {
+        const NAME: &'static str = "Health";
+
+        fn save(&self) -> String {
+            self.0.to_string()
+        }
+
+        fn load(value: &str) -> Result<Self, String> {
+            value.parse().map(Health).map_err(|err| format!("{}", err))
+        }
+    }
+
+    impl Persist for Turn {
+        const NAME: &'static str = "Turn";
+
+        fn save(&self) -> String {
+            format!("turn\n{}", self.0)
+        }
+
+        fn load(value: &str) -> Result<Self, String> {
+            let number = value.strip_prefix("turn\n").ok_or("missing turn line")?;
+            number.parse().map(Turn).map_err(|err| format!("{}", err))
+        }
+    }
+
+    #[derive(Component)]
+    struct Hitpoints(pub u32);
+
+    impl Persist for Hitpoints {
+        const NAME: &'static str = "Health";
+
+        fn save(&self) -> String {
+            self.0.to_string()
+        }
+
+        fn load(value: &str) -> Result<Self, String> {
+            value
+                .parse()
+                .map(Hitpoints)
+                .map_err(|err| format!("{}", err))
+        }
+    }
+
+    fn registered_world() -> World {
+        let mut world = World::new();
+        world.register_saved_component::<Health>().unwrap();
+        world.register_saved_resource::<Turn>().unwrap();
+        world.register_component::<Unsaved>();
+        world
+    }
+
+    fn load(text: &str) -> Result<World, SaveError> {
+        let mut world = registered_world();
+        world.load(text.as_bytes())?;
+        Ok(world)
+    }
+
+    #[test]
+    fn save_format_is_readable() -> Result<(), SaveError> {
+        let mut world = registered_world();
+        world.add_resource(Turn(7));
+        let first = world.spawn((Health(10), Unsaved)).unwrap();
+        world.spawn((Health(20),)).unwrap();
+        world.despawn(first).unwrap();
+        world.spawn((Unsaved,)).unwrap();
+        world.despawn(first).ok();
+
+        let mut saved = vec![];
+        world.save(&mut saved)?;
+        assert_eq!(
+            String::from_utf8(saved).unwrap(),
+            "concoeur-save 1\n\
+             spawned 3\n\
+             resource Turn\n  turn\n  7\n\
+             entity 0 1 2\n\
+             entity 1 0 1\ncomponent Health\n  20\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_names_are_rejected() -> Result<(), SaveError> {
+        let mut world = registered_world();
+        world.register_saved_component::<Health>()?;
+        let err = world.register_saved_component::<Hitpoints>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Health and Hitpoints are both saved as \"Health\""
+        );
+        assert_eq!(world.storage_kind::<Hitpoints>(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn saving_borrowed_values_fails() {
+        let mut world = registered_world();
+        world.add_resource(Turn(1));
+        let entity = world.spawn((Health(10),)).unwrap();
+        let health = world.entity(entity).unwrap().get_mut::<Health>().unwrap();
+        assert!(matches!(
+            world.save(&mut vec![]),
+            Err(SaveError::Ecs(EcsError::AlreadyBorrowed { entity: borrowed, .. }))
+                if borrowed == entity
+        ));
+        drop(health);
+
+        let turn = world.resource_mut::<Turn>().unwrap();
+        assert!(matches!(
+            world.save(&mut vec![]),
+            Err(SaveError::Ecs(EcsError::ResourceBorrowed { .. }))
+        ));
+        drop(turn);
+        assert!(world.save(&mut vec![]).is_ok());
+    }
+
+    #[test]
+    fn load_restores_handles() -> Result<(), SaveError> {
+        let world = load(
+            "concoeur-save 1\n\
+             resource Turn\n  turn\n  3\n\
+             dead 0 4\n\
+             entity 1 2 1\ncomponent Health\n  5\n",
+        )?;
+
+        let entity = Entity {
+            index: 1,
+            generation: 2,
+        };
+        assert_eq!(
+            *world.entity(entity).unwrap().get::<Health>().unwrap(),
+            Health(5)
+        );
+        assert_eq!(world.get_resource::<Turn>().as_deref(), Some(&Turn(3)));
+        assert!(!world.is_alive(Entity {
+            index: 0,
+            generation: 3
+        }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_loads_leave_the_world_alone() -> Result<(), SaveError> {
+        let mut world = registered_world();
+        world.add_resource(Turn(1));
+        world.on_add::<Health>(|world, _| world.resource_mut::<Turn>().unwrap().0 += 10);
+        let save = |health: &str| {
+            format!(
+                "concoeur-save 1\n\
+                 resource Turn\n  turn\n  3\n\
+                 entity 0 0 0\ncomponent Health\n  5\n\
+                 entity 1 0 1\ncomponent Health\n  {}\n",
+                health
+            )
+        };
+
+        assert!(world.load(save("lots").as_bytes()).is_err());
+        assert_eq!(world.slots().count(), 0);
+        assert_eq!(world.resource::<Turn>().unwrap().0, 1);
+
+        world.load(save("6").as_bytes())?;
+        let entity = Entity {
+            index: 1,
+            generation: 0,
+        };
+        assert_eq!(
+            *world.entity(entity).unwrap().get::<Health>().unwrap(),
+            Health(6)
+        );
+        // Hooks run once the loaded resources are in place.
+        assert_eq!(world.resource::<Turn>().unwrap().0, 23);
+
+        Ok(())
+    }
+
+    #[test]
+    fn spawn_order_survives_saving() -> Result<(), SaveError> {
+        let mut world = registered_world();
+        let first = world.spawn((Health(1),)).unwrap();
+        world.spawn((Health(2),)).unwrap();
+        world.despawn(first).unwrap();
+        // Reuses the first slot but comes last.
+        world.spawn((Health(3),)).unwrap();
+
+        let mut saved = vec![];
+        world.save(&mut saved)?;
+        let mut loaded = load(std::str::from_utf8(&saved).unwrap())?;
+        loaded.spawn((Health(4),)).unwrap();
+        let healths: Vec<u32> = loaded
+            .query::<&Health>()
+            .iter()?
+            .map(|item| item.map(|health| health.0))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(healths, vec![2, 3, 4]);
+
+        assert_eq!(
+            load("concoeur-save 1\ndead 0 0\nentity 1 0 0\nfree 1\n")
+                .err()
+                .map(|err| err.to_string()),
+            Some("line 4: free slots don't match the dead entities".to_string())
+        );
+        assert_eq!(
+            load("concoeur-save 1\nspawned 1\nentity 0 0 4\n")
+                .err()
+                .map(|err| err.to_string()),
+            Some("line 2: spawn count is below a saved spawn sequence".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn hierarchy_survives_saving() -> Result<(), SaveError> {
+        let mut world = registered_world();
+        let potion = world.spawn((Health(1),)).unwrap();
+        let player = world.spawn((Health(10),)).unwrap();
+        world.set_parent(potion, player).unwrap();
+
+        let mut saved = vec![];
+        world.save(&mut saved)?;
+        let loaded = load(std::str::from_utf8(&saved).unwrap())?;
+        assert_eq!(loaded.parent(potion), Some(player));
+        assert_eq!(loaded.children(player), &[potion]);
+
+        assert_eq!(
+            load("concoeur-save 1\nentity 0 0 0\nparent 0 0\n")
+                .err()
+                .map(|err| err.to_string()),
+            Some(format!(
+                "line 3: {}",
+                EcsError::HierarchyCycle {
+                    child: potion,
+                    parent: potion
+                }
+            ))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_errors_name_the_line() {
+        let error = |text: &str| load(text).err().map(|err| err.to_string());
+
+        assert_eq!(
+            error("concoeur-save 2\n"),
+            Some("unsupported save version \"concoeur-save 2\"".to_string())
+        );
+        assert_eq!(
+            error("concoeur-save 1\nentity 0 0 0\ncomponent Health\n  lots\n"),
+            Some("line 3: invalid digit found in string".to_string())
+        );
+        assert_eq!(
+            error("concoeur-save 1\nentity 0 0 0\ncomponent Mana\n"),
+            Some("line 3: unknown component Mana".to_string())
+        );
+        assert_eq!(
+            error("concoeur-save 1\nentity 1 0 1\n"),
+            Some("line 2: entity slots are out of order".to_string())
+        );
+        assert_eq!(
+            error("concoeur-save 1\n  5\n"),
+            Some("line 2: value without a component or resource".to_string())
+        );
+        assert_eq!(
+            error("concoeur-save 1\nentity 0 0 0\n  5\n"),
+            Some("line 3: value without a component or resource".to_string())
+        );
+        assert_eq!(
+            error("concoeur-save 1\nentity 0 0 0\nentity 1 0 1\nparent 0 0\n  5\n"),
+            Some("line 5: value without a component or resource".to_string())
+        );
+
+        let mut world = registered_world();
+        world.spawn((Health(1),)).unwrap();
+        assert!(matches!(
+            world.load("concoeur-save 1\n".as_bytes()),
+            Err(SaveError::WorldNotEmpty)
+        ));
+    }
+}
@@ -0,0 +1,261 @@
+//! Entity templates ("raws") described in the same record format as saves:
+//!
+//! ```text
+//! # Monsters
+//! prefab goblin
+//! component Renderable
+//!   g
+//! component Monster
+//! ```
+//!
+//! Components are looked up by their `Persist::NAME`, so only types
+//! registered with `World::register_saved_component` can appear in a prefab.
+
+use std::{any::TypeId, collections::HashMap, io::BufRead};
+
+use super::{
+    persist::{read_records, LoadError, Persisters, SaveError},
+    EcsError, Entity, World,
+};
+
+#[derive(Debug, Clone)]
+struct PrefabComponent {
+    type_id: TypeId,
+    load: fn(&mut World, Entity, &str) -> Result<(), LoadError>,
+    value: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Prefabs {
+    prefabs: HashMap<String, Vec<PrefabComponent>>,
+}
+
+impl Prefabs {
+    /// Adds the prefabs in `input`. Every component value is parsed here, so
+    /// spawning a prefab can't fail halfway.
+    pub fn load(&mut self, persisters: &Persisters, input: impl BufRead) -> Result<(), SaveError> {
+        let mut loaded: Vec<(String, Vec<PrefabComponent>)> = vec![];
+        for record in read_records(input.lines(), 1)? {
+            match record.words().as_slice() {
+                ["prefab", name] => {
+                    record.check_no_value()?;
+                    let duplicate = self.prefabs.contains_key(*name)
+                        || loaded.iter().any(|(loaded, _)| loaded == name);
+                    if duplicate {
+                        return Err(record.error(&format!("prefab {} is defined twice", name)));
+                    }
+                    loaded.push((name.to_string(), vec![]));
+                }
+                ["component", name] => {
+                    let Some((prefab, components)) = loaded.last_mut() else {
+                        return Err(record.error("component outside of a prefab"));
+                    };
+                    let component = persisters
+                        .component(name)
+                        .ok_or_else(|| record.error(&format!("unknown component {}", name)))?;
+                    if components
+                        .iter()
+                        .any(|loaded| loaded.type_id == component.type_id)
+                    {
+                        return Err(record.error(&format!("prefab {} has {} twice", prefab, name)));
+                    }
+                    let value = record.value();
+                    (component.check)(&value).map_err(|message| record.error(&message))?;
+                    components.push(PrefabComponent {
+                        type_id: component.type_id,
+                        load: component.load,
+                        value,
+                    });
+                }
+                _ => return Err(record.error("unrecognized line")),
+            }
+        }
+        self.prefabs.extend(loaded);
+        Ok(())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.prefabs.contains_key(name)
+    }
+
+    /// Inserts the named prefab's components, if there is one, into `entity`,
+    /// except the types in `overridden`.
+    pub(super) fn insert(
+        &self,
+        name: &str,
+        world: &mut World,
+        entity: Entity,
+        overridden: &[TypeId],
+    ) -> Result<(), EcsError> {
+        let components = self.prefabs.get(name).into_iter().flatten();
+        for component in components.filter(|component| !overridden.contains(&component.type_id)) {
+            match (component.load)(world, entity, &component.value) {
+                Ok(()) => {}
+                Err(LoadError::Ecs(err)) => return Err(err),
+                Err(LoadError::Invalid(message)) => {
+                    unreachable!(
+                        "prefab components are checked when they are loaded, {}",
+                        message
+                    )
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ecs::{persist::Persist, Component, EcsError};
+
+    #[derive(Debug, PartialEq, Component)]
+    struct Glyph(pub char);
+    #[derive(Debug, PartialEq, Component)]
+    struct Health(pub u32);
+    #[derive(Debug, PartialEq, Component)]
+    struct Level(pub u8);
+
+    impl Persist for Glyph {
+        const NAME: &'static str = "Glyph";
+
+        fn save(&self) -> String {
+            self.0.to_string()
+        }
+
+        fn load(value: &str) -> Result<Self, String> {
+            value
+                .parse()
+                .map(Glyph)
+                .map_err(|_| format!("bad glyph {:?}", value))
+        }
+    }
+
+    impl Persist for Health {
+        const NAME: &'static str = "Health";
+
+        fn save(&self) -> String {
+            self.0.to_string()
+        }
+
+        fn load(value: &str) -> Result<Self, String> {
+            value
+                .parse()
+                .map(Health)
+                .map_err(|_| format!("bad health {:?}", value))
+        }
+    }
+
+    const RAWS: &str = "\
+# Monsters
+prefab goblin
+component Glyph
+  g
+component Health
+  7
+
+prefab bat
+component Glyph
+  b
+";
+
+    fn prefab_world() -> World {
+        let mut world = World::new();
+        world.register_saved_component::<Glyph>().unwrap();
+        world.register_saved_component::<Health>().unwrap();
+        world
+    }
+
+    #[test]
+    fn spawn_prefabs() -> Result<(), EcsError> {
+        let mut world = prefab_world();
+        world.load_prefabs(RAWS.as_bytes()).unwrap();
+
+        let goblin = world.spawn_prefab("goblin", ())?;
+        let bat = world.spawn_prefab("bat", (Health(2), Level(5)))?;
+
+        let goblin = world.entity(goblin)?;
+        assert_eq!(*goblin.get::<Glyph>()?, Glyph('g'));
+        assert_eq!(*goblin.get::<Health>()?, Health(7));
+        let bat = world.entity(bat)?;
+        assert_eq!(*bat.get::<Health>()?, Health(2));
+        assert_eq!(*bat.get::<Level>()?, Level(5));
+        assert_eq!(
+            world.spawn_prefab("dragon", ()).err(),
+            Some(EcsError::UnknownPrefab {
+                name: "dragon".to_string()
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn hooks_can_despawn_a_prefab() -> Result<(), EcsError> {
+        let mut world = prefab_world();
+        world.load_prefabs(RAWS.as_bytes()).unwrap();
+        world.on_add::<Glyph>(|world, entity| world.despawn(entity).unwrap());
+
+        let goblin = world.spawn_prefab("goblin", (Health(2),))?;
+        assert!(!world.is_alive(goblin));
+        assert_eq!(world.query::<&Health>().iter()?.count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn bundle_components_are_added_instead_of_the_prefabs() -> Result<(), EcsError> {
+        let mut world = prefab_world();
+        world.load_prefabs(RAWS.as_bytes()).unwrap();
+        world.add_resource(Vec::<&str>::new());
+        world.on_add::<Health>(|world, _| world.resource_mut::<Vec<&str>>().unwrap().push("add"));
+        world.on_replace::<Health>(|world, _| {
+            world.resource_mut::<Vec<&str>>().unwrap().push("replace")
+        });
+
+        let goblin = world.spawn_prefab("goblin", (Health(2),))?;
+        assert_eq!(*world.entity(goblin)?.get::<Health>()?, Health(2));
+        assert_eq!(*world.entity(goblin)?.get::<Glyph>()?, Glyph('g'));
+        assert_eq!(*world.resource::<Vec<&str>>()?, vec!["add"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_raws_name_the_line() {
+        let error = |raws: &str| {
+            prefab_world()
+                .load_prefabs(raws.as_bytes())
+                .err()
+                .map(|err| err.to_string())
+        };
+
+        assert_eq!(
+            error("prefab goblin\ncomponent Mana\n  3\n"),
+            Some("line 2: unknown component Mana".to_string())
+        );
+        assert_eq!(
+            error("# Goblin\nprefab goblin\ncomponent Glyph\n  gg\n"),
+            Some("line 3: bad glyph \"gg\"".to_string())
+        );
+        assert_eq!(
+            error("component Glyph\n  g\n"),
+            Some("line 1: component outside of a prefab".to_string())
+        );
+        assert_eq!(
+            error("prefab bat\n\nprefab bat\n"),
+            Some("line 3: prefab bat is defined twice".to_string())
+        );
+        assert_eq!(
+            error(
+                "prefab bat
+component Health
+  1
+component Health
+  2
+"
+            ),
+            Some("line 4: prefab bat has Health twice".to_string())
+        );
+    }
+}
@@ -1,36 +1,126 @@
 use std::{
-    any::{Any, TypeId},
+    any::{type_name, Any, TypeId},
+    cell::{Ref, RefCell, RefMut},
     collections::HashMap,
 };
 
+use super::error::EcsError;
+
+pub type Resource = RefCell<Box<dyn Any>>;
+
+/// `type_name` without the module path, the resource counterpart of
+/// `Component::TYPE_NAME`, e.g. `Vec<alloc::string::String>` for a
+/// `Vec<String>`. Names that don't start with a path, like tuples, are
+/// left alone.
+pub(crate) fn short_type_name<T: ?Sized>() -> &'static str {
+    let name = type_name::<T>();
+    let path = &name[..name.find('<').unwrap_or(name.len())];
+    if !path
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == ':')
+    {
+        return name;
+    }
+    path.rfind("::").map_or(name, |end| &name[end + 2..])
+}
+
+/// One value per type. Each sits in a `RefCell`, so like components they
+/// can be borrowed mutably through a shared reference; conflicting borrows
+/// are caught at runtime.
 #[derive(Debug, Default)]
 pub struct Resources {
-    data: HashMap<TypeId, Box<dyn Any>>,
+    data: HashMap<TypeId, Resource>,
+    names: HashMap<TypeId, &'static str>,
 }
 
 impl Resources {
-    pub fn add(&mut self, data: impl Any) {
+    /// Adds `data`, replacing any resource of the same type.
+    pub fn add<T: Any>(&mut self, data: T) {
         let type_id = data.type_id();
-        self.data.insert(type_id, Box::new(data));
+        self.names.insert(type_id, short_type_name::<T>());
+        self.data.insert(type_id, RefCell::new(Box::new(data)));
     }
 
-    pub fn get_ref<T: Any>(&self) -> Option<&T> {
-        let type_id = TypeId::of::<T>();
-        if let Some(data) = self.data.get(&type_id) {
-            data.downcast_ref()
-        } else {
-            None
-        }
+    /// Moves every resource in `other` into this one, replacing those of the
+    /// same type.
+    pub fn append(&mut self, other: Resources) {
+        self.data.extend(other.data);
+        self.names.extend(other.names);
+    }
+
+    /// The `T` resource, unless there is none or it is mutably borrowed.
+    pub fn get_ref<T: Any>(&self) -> Option<Ref<'_, T>> {
+        self.borrow::<T>().ok()
     }
 
     pub fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
         let type_id = TypeId::of::<T>();
         if let Some(data) = self.data.get_mut(&type_id) {
-            data.downcast_mut()
+            data.get_mut().downcast_mut()
         } else {
             None
         }
     }
+
+    pub fn borrow<T: Any>(&self) -> Result<Ref<'_, T>, EcsError> {
+        let data = self
+            .cell::<T>()?
+            .try_borrow()
+            .map_err(|_| EcsError::ResourceBorrowed {
+                type_name: short_type_name::<T>(),
+            })?;
+        Ok(Ref::map(data, |data| data.downcast_ref::<T>().unwrap()))
+    }
+
+    pub fn borrow_mut<T: Any>(&self) -> Result<RefMut<'_, T>, EcsError> {
+        let data = self
+            .cell::<T>()?
+            .try_borrow_mut()
+            .map_err(|_| EcsError::ResourceBorrowed {
+                type_name: short_type_name::<T>(),
+            })?;
+        Ok(RefMut::map(data, |data| data.downcast_mut::<T>().unwrap()))
+    }
+
+    fn cell<T: Any>(&self) -> Result<&Resource, EcsError> {
+        self.data
+            .get(&TypeId::of::<T>())
+            .ok_or(EcsError::ResourceMissing {
+                type_name: short_type_name::<T>(),
+            })
+    }
+
+    /// Type id, type name and cell of every resource, sorted by name.
+    pub(crate) fn entries(&self) -> Vec<(TypeId, &'static str, &Resource)> {
+        let mut entries: Vec<_> = self
+            .data
+            .iter()
+            .map(|(type_id, data)| (*type_id, self.names[type_id], data))
+            .collect();
+        entries.sort_by_key(|(_, name, _)| *name);
+        entries
+    }
+
+    pub fn contains<T: Any>(&self) -> bool {
+        self.data.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn remove<T: Any>(&mut self) -> Option<T> {
+        self.names.remove(&TypeId::of::<T>());
+        let data = self.data.remove(&TypeId::of::<T>())?;
+        data.into_inner().downcast().ok().map(|data| *data)
+    }
+
+    /// The `T` resource, added first with `insert` if there is none.
+    pub fn get_or_insert_with<T: Any>(&mut self, insert: impl FnOnce() -> T) -> &mut T {
+        self.names.insert(TypeId::of::<T>(), short_type_name::<T>());
+        self.data
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| RefCell::new(Box::new(insert())))
+            .get_mut()
+            .downcast_mut()
+            .unwrap()
+    }
 }
 
 #[cfg(test)]
@@ -42,6 +132,7 @@ mod test {
         let resources = initialize_resource();
 
         let stored_resource = resources.data.get(&TypeId::of::<WorldWidth>()).unwrap();
+        let stored_resource = stored_resource.borrow();
         let world_width = stored_resource.downcast_ref::<WorldWidth>().unwrap();
         assert_eq!(world_width.0, 100.0);
     }
@@ -52,7 +143,7 @@ mod test {
 
         if let Some(world_width) = resources.get_ref::<WorldWidth>() {
             assert_eq!(world_width.0, 100.0);
-        }
+        };
     }
 
     #[test]
@@ -66,6 +157,53 @@ mod test {
         assert_eq!(world_width.0, 101.0);
     }
 
+    #[test]
+    fn borrow_through_shared_reference() -> Result<(), EcsError> {
+        let resources = initialize_resource();
+        let mut world_width = resources.borrow_mut::<WorldWidth>()?;
+        world_width.0 += 1.0;
+        assert_eq!(
+            resources.borrow::<WorldWidth>().err(),
+            Some(EcsError::ResourceBorrowed {
+                type_name: "WorldWidth"
+            })
+        );
+        assert!(resources.get_ref::<WorldWidth>().is_none());
+        drop(world_width);
+
+        assert_eq!(resources.borrow::<WorldWidth>()?.0, 101.0);
+        assert_eq!(
+            resources.borrow::<u32>().err(),
+            Some(EcsError::ResourceMissing { type_name: "u32" })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn type_names_drop_the_module_path() {
+        assert_eq!(short_type_name::<WorldWidth>(), "WorldWidth");
+        assert_eq!(
+            short_type_name::<Vec<String>>(),
+            "Vec<alloc::string::String>"
+        );
+        assert_eq!(
+            short_type_name::<(u8, String)>(),
+            type_name::<(u8, String)>()
+        );
+    }
+
+    #[test]
+    fn remove_and_insert_resources() {
+        let mut resources = initialize_resource();
+        assert_eq!(resources.get_or_insert_with(|| 1u32), &mut 1);
+        *resources.get_or_insert_with(|| 5u32) += 1;
+        assert_eq!(resources.remove::<u32>(), Some(2));
+        assert!(!resources.contains::<u32>());
+        assert!(resources.contains::<WorldWidth>());
+        assert_eq!(resources.remove::<u32>(), None);
+    }
+
     fn initialize_resource() -> Resources {
         let mut resources = Resources::default();
         let world_width = WorldWidth(100.0);
@@ -0,0 +1,297 @@
+use std::{cmp::Ordering, collections::HashMap, fmt};
+
+use super::{EcsError, World};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stage {
+    Input,
+    Ai,
+    Movement,
+    Combat,
+    Cleanup,
+    Render,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [
+        Stage::Input,
+        Stage::Ai,
+        Stage::Movement,
+        Stage::Combat,
+        Stage::Cleanup,
+        Stage::Render,
+    ];
+}
+
+pub trait System {
+    fn run(&mut self, world: &mut World);
+}
+
+impl<F: FnMut(&mut World)> System for F {
+    fn run(&mut self, world: &mut World) {
+        self(world)
+    }
+}
+
+pub struct SystemEntry {
+    name: &'static str,
+    stage: Stage,
+    system: Box<dyn System>,
+    after: Vec<&'static str>,
+    before: Vec<&'static str>,
+}
+
+impl SystemEntry {
+    /// Runs this system after the named one. Systems in an earlier stage
+    /// already run first, so the constraint only matters within a stage.
+    pub fn after(&mut self, name: &'static str) -> &mut Self {
+        self.after.push(name);
+        self
+    }
+
+    pub fn before(&mut self, name: &'static str) -> &mut Self {
+        self.before.push(name);
+        self
+    }
+}
+
+/// Systems grouped by stage. Stages run in the order of `Stage::ALL`, and
+/// systems within a stage run in insertion order unless `after`/`before`
+/// constraints say otherwise.
+#[derive(Default)]
+pub struct Schedule {
+    systems: Vec<SystemEntry>,
+}
+
+impl Schedule {
+    pub fn add_system(
+        &mut self,
+        stage: Stage,
+        name: &'static str,
+        system: impl System + 'static,
+    ) -> &mut SystemEntry {
+        self.systems.push(SystemEntry {
+            name,
+            stage,
+            system: Box::new(system),
+            after: vec![],
+            before: vec![],
+        });
+        self.systems.last_mut().unwrap()
+    }
+
+    pub fn append(&mut self, other: Schedule) {
+        self.systems.extend(other.systems);
+    }
+
+    /// Runs every system, applying commands after each one. Commands that
+    /// fail are kept in `World::command_errors` rather than stopping the run.
+    pub fn run(&mut self, world: &mut World) -> Result<(), EcsError> {
+        for index in self.order()? {
+            self.systems[index].system.run(world);
+            world.apply_commands_logged();
+        }
+        Ok(())
+    }
+
+    fn order(&self) -> Result<Vec<usize>, EcsError> {
+        let mut indexes_by_name = HashMap::new();
+        for (index, entry) in self.systems.iter().enumerate() {
+            if indexes_by_name.insert(entry.name, index).is_some() {
+                return Err(EcsError::DuplicateSystem { name: entry.name });
+            }
+        }
+
+        // Edge `from -> to` means `from` has to run before `to`.
+        let mut edges = vec![vec![]; self.systems.len()];
+        let mut incoming = vec![0; self.systems.len()];
+        for (index, entry) in self.systems.iter().enumerate() {
+            let afters = entry.after.iter().map(|name| (name, true));
+            let befores = entry.before.iter().map(|name| (name, false));
+            for (name, is_after) in afters.chain(befores) {
+                let other = *indexes_by_name
+                    .get(name)
+                    .ok_or(EcsError::UnknownSystem { name })?;
+                let (from, to) = if is_after {
+                    (other, index)
+                } else {
+                    (index, other)
+                };
+                match self.systems[from].stage.cmp(&self.systems[to].stage) {
+                    Ordering::Less => {}
+                    Ordering::Greater => {
+                        return Err(EcsError::OrderingContradictsStage { name: entry.name })
+                    }
+                    Ordering::Equal => {
+                        edges[from].push(to);
+                        incoming[to] += 1;
+                    }
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(self.systems.len());
+        for stage in Stage::ALL {
+            let mut ready: Vec<usize> = (0..self.systems.len())
+                .filter(|index| self.systems[*index].stage == stage && incoming[*index] == 0)
+                .collect();
+            let stage_len = self
+                .systems
+                .iter()
+                .filter(|entry| entry.stage == stage)
+                .count();
+            let mut stage_order = vec![];
+            while !ready.is_empty() {
+                // Take the earliest inserted system that is ready.
+                let next = ready.remove(0);
+                stage_order.push(next);
+                for to in &edges[next] {
+                    incoming[*to] -= 1;
+                    if incoming[*to] == 0 {
+                        let position = ready.partition_point(|index| index < to);
+                        ready.insert(position, *to);
+                    }
+                }
+            }
+            if stage_order.len() != stage_len {
+                return Err(EcsError::OrderingCycle);
+            }
+            order.extend(stage_order);
+        }
+        Ok(order)
+    }
+}
+
+impl fmt::Debug for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.systems.iter().map(|entry| (entry.stage, entry.name)))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct RunLog(pub Vec<&'static str>);
+
+    fn log(name: &'static str) -> impl FnMut(&mut World) {
+        move |world: &mut World| world.get_resource_mut::<RunLog>().unwrap().0.push(name)
+    }
+
+    fn initialize_world() -> World {
+        let mut world = World::new();
+        world.add_resource(RunLog::default());
+        world
+    }
+
+    fn run_log(world: &World) -> Vec<&'static str> {
+        world.get_resource::<RunLog>().unwrap().0.clone()
+    }
+
+    #[test]
+    fn stages_run_in_order() -> Result<(), EcsError> {
+        let mut world = initialize_world();
+        world.add_system(Stage::Render, "render", log("render"));
+        world.add_system(Stage::Cleanup, "cleanup", log("cleanup"));
+        world.add_system(Stage::Input, "input", log("input"));
+        world.add_system(Stage::Ai, "ai", log("ai"));
+
+        world.run_schedule()?;
+        assert_eq!(run_log(&world), vec!["input", "ai", "cleanup", "render"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn ordering_constraints_within_stage() -> Result<(), EcsError> {
+        let mut world = initialize_world();
+        world
+            .add_system(Stage::Combat, "damage", log("damage"))
+            .after("attack");
+        world.add_system(Stage::Combat, "attack", log("attack"));
+        world
+            .add_system(Stage::Combat, "death", log("death"))
+            .before("attack")
+            .after("movement");
+        world.add_system(Stage::Movement, "movement", log("movement"));
+
+        world.run_schedule()?;
+        assert_eq!(
+            run_log(&world),
+            vec!["movement", "death", "attack", "damage"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_ordering_is_an_error() {
+        let mut world = initialize_world();
+        world.add_system(Stage::Ai, "a", log("a")).after("b");
+        world.add_system(Stage::Ai, "b", log("b")).after("a");
+        assert_eq!(world.run_schedule(), Err(EcsError::OrderingCycle));
+
+        let mut world = initialize_world();
+        world.add_system(Stage::Ai, "a", log("a")).after("missing");
+        assert_eq!(
+            world.run_schedule(),
+            Err(EcsError::UnknownSystem { name: "missing" })
+        );
+
+        let mut world = initialize_world();
+        world.add_system(Stage::Input, "a", log("a")).after("b");
+        world.add_system(Stage::Render, "b", log("b"));
+        assert_eq!(
+            world.run_schedule(),
+            Err(EcsError::OrderingContradictsStage { name: "a" })
+        );
+        assert!(run_log(&world).is_empty());
+    }
+
+    #[test]
+    fn failed_commands_do_not_stop_the_run() -> Result<(), EcsError> {
+        let mut world = initialize_world();
+        let entity = world.create_entity().current_entity();
+        world.add_system(Stage::Combat, "kill", move |world: &mut World| {
+            world.commands().despawn(entity);
+            world.commands().despawn(entity);
+        });
+        world.add_system(Stage::Render, "render", log("render"));
+
+        world.run_schedule()?;
+        assert_eq!(run_log(&world), vec!["render"]);
+        assert!(!world.is_alive(entity));
+        assert_eq!(world.command_errors(), [EcsError::StaleEntity { entity }]);
+
+        world.run_schedule()?;
+        assert_eq!(run_log(&world), vec!["render", "render"]);
+        // Both despawns fail now; errors from the first run are gone.
+        assert_eq!(world.command_errors().len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn systems_added_while_running_are_kept() -> Result<(), EcsError> {
+        let mut world = initialize_world();
+        world.add_system(Stage::Input, "spawner", |world: &mut World| {
+            if world.get_resource::<RunLog>().unwrap().0.is_empty() {
+                world.add_system(Stage::Render, "late", log("late"));
+            }
+            world
+                .get_resource_mut::<RunLog>()
+                .unwrap()
+                .0
+                .push("spawner");
+        });
+
+        world.run_schedule()?;
+        world.run_schedule()?;
+        assert_eq!(run_log(&world), vec!["spawner", "spawner", "late"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+//! In-memory copies of a `World` to go back to, e.g. to simulate a move and
+//! undo it, or to step back a turn while debugging.
+//!
+//! Only components and resources registered with
+//! `register_snapshot_component` and `register_snapshot_resource` are
+//! copied. Entity slots, generations and parent/child relations always are.
+
+use std::{
+    any::{Any, TypeId},
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+};
+
+use super::entity::{CloneComponent, Component, Entities};
+use super::{EcsError, World};
+
+/// State captured by `World::snapshot`. It can be restored any number of
+/// times.
+#[derive(Debug)]
+pub struct Snapshot {
+    pub(super) entities: Entities,
+    /// One entry per registered resource, `None` where the world had none.
+    resources: Vec<ResourceCopy>,
+}
+
+type ResourceCopy = Option<Box<dyn Any>>;
+
+#[derive(Debug, Clone, Copy)]
+struct ResourceSnapshotter {
+    type_id: TypeId,
+    take: fn(&World) -> Result<ResourceCopy, EcsError>,
+    restore: fn(&mut World, Option<&dyn Any>),
+}
+
+fn clone_component<T: Clone + Any>(component: &dyn Any) -> Component {
+    Rc::new(RefCell::new(component.downcast_ref::<T>().unwrap().clone()))
+}
+
+fn take_resource<T: Clone + Any>(world: &World) -> Result<ResourceCopy, EcsError> {
+    match world.resource::<T>() {
+        Ok(resource) => Ok(Some(Box::new(resource.clone()))),
+        Err(EcsError::ResourceMissing { .. }) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn restore_resource<T: Clone + Any>(world: &mut World, resource: Option<&dyn Any>) {
+    world.remove_resource::<T>();
+    if let Some(resource) = resource {
+        world.add_resource(resource.downcast_ref::<T>().unwrap().clone());
+    }
+}
+
+/// Which components and resources a `World` snapshots.
+#[derive(Debug, Default, Clone)]
+pub(super) struct Snapshotters {
+    pub components: HashMap<TypeId, CloneComponent>,
+    resources: Vec<ResourceSnapshotter>,
+}
+
+impl Snapshotters {
+    pub fn add_component<T: Clone + Any>(&mut self) {
+        self.components
+            .insert(TypeId::of::<T>(), clone_component::<T>);
+    }
+
+    /// Includes `type_id` components like `from` does, unless they are
+    /// included already.
+    pub fn copy_component(&mut self, from: &Snapshotters, type_id: TypeId) {
+        if let Some(clone) = from.components.get(&type_id) {
+            self.components.entry(type_id).or_insert(*clone);
+        }
+    }
+
+    pub fn add_resource<T: Clone + Any>(&mut self) {
+        let type_id = TypeId::of::<T>();
+        if self.resources.iter().all(|entry| entry.type_id != type_id) {
+            self.resources.push(ResourceSnapshotter {
+                type_id,
+                take: take_resource::<T>,
+                restore: restore_resource::<T>,
+            });
+        }
+    }
+
+    pub fn snapshot(&self, world: &World, entities: &Entities) -> Result<Snapshot, EcsError> {
+        let resources = self
+            .resources
+            .iter()
+            .map(|entry| (entry.take)(world))
+            .collect::<Result<_, _>>()?;
+        Ok(Snapshot {
+            entities: entities.clone_with(&self.components)?,
+            resources,
+        })
+    }
+
+    /// Puts back the resources in `snapshot`. Ones registered after it was
+    /// taken are left alone.
+    pub fn restore_resources(&self, world: &mut World, snapshot: &Snapshot) {
+        for (entry, resource) in self.resources.iter().zip(&snapshot.resources) {
+            (entry.restore)(world, resource.as_deref());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ecs::Component;
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Component)]
+    struct Health(pub u32);
+    #[derive(Debug, Clone, PartialEq)]
+    struct Turn(pub u32);
+    #[derive(Component)]
+    struct Uncloned;
+    #[derive(Clone, Component)]
+    struct Cloned;
+
+    fn registered_world() -> World {
+        let mut world = World::new();
+        world.register_snapshot_component::<Health>();
+        world.register_snapshot_resource::<Turn>();
+        world.register_component::<Uncloned>();
+        world
+    }
+
+    #[test]
+    fn restore_undoes_everything_since_the_snapshot() -> Result<(), EcsError> {
+        let mut world = registered_world();
+        world.add_resource(Turn(1));
+        let player = world.spawn((Health(10), Uncloned))?;
+        let potion = world.spawn((Health(1),))?;
+        let snapshot = world.snapshot()?;
+
+        for _ in 0..2 {
+            world.resource_mut::<Turn>()?.0 += 1;
+            world.entity_mut(player)?.get_mut::<Health>()?.0 = 0;
+            world.despawn(potion)?;
+            let ghost = world.spawn((Health(3),))?;
+            world.set_parent(ghost, player)?;
+
+            world.restore(&snapshot);
+            assert_eq!(world.resource::<Turn>()?.0, 1);
+            assert_eq!(*world.entity(player)?.get::<Health>()?, Health(10));
+            assert_eq!(*world.entity(potion)?.get::<Health>()?, Health(1));
+            assert!(!world.is_alive(ghost));
+            assert!(world.children(player).is_empty());
+            assert!(!world.has_component::<Uncloned>(player));
+        }
+        // Slots created after the snapshot are gone.
+        assert_eq!(world.spawn((Uncloned,))?.index, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_fails_on_mutable_borrows() -> Result<(), EcsError> {
+        let mut world = registered_world();
+        let snapshot = world.snapshot()?;
+        world.add_resource(Turn(1));
+        let player = world.spawn((Health(10),))?;
+
+        let health = world.entity(player)?.get_mut::<Health>()?;
+        assert!(matches!(
+            world.snapshot(),
+            Err(EcsError::AlreadyBorrowed { .. })
+        ));
+        drop(health);
+        let turn = world.resource_mut::<Turn>()?;
+        assert!(matches!(
+            world.snapshot(),
+            Err(EcsError::ResourceBorrowed { .. })
+        ));
+        drop(turn);
+
+        world.restore(&snapshot);
+        assert!(!world.contains_resource::<Turn>());
+        assert!(!world.is_alive(player));
+        // Restoring keeps registrations made after the snapshot.
+        world.register_snapshot_component::<Cloned>();
+        world.restore(&snapshot);
+        world.create_entity().with_component(Cloned)?;
+
+        Ok(())
+    }
+}
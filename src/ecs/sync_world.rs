@@ -0,0 +1,538 @@
+use std::{
+    any::{Any, TypeId},
+    cell::RefCell,
+    collections::HashMap,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError},
+};
+
+use super::{parallel::Access, resource::short_type_name, Component, EcsError, Entity};
+
+type Erased = Box<dyn Any + Send + Sync>;
+
+thread_local! {
+    /// Name and `Access` of the parallel system running on this thread.
+    static DECLARED: RefCell<Option<(&'static str, Access)>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with the accessors limited to what `access` declares.
+pub(crate) fn with_access<R>(system: &'static str, access: &Access, f: impl FnOnce() -> R) -> R {
+    struct Reset(Option<(&'static str, Access)>);
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            DECLARED.set(self.0.take());
+        }
+    }
+
+    let _reset = Reset(DECLARED.replace(Some((system, access.clone()))));
+    f()
+}
+
+/// Outside of a `ParallelSchedule` every access is allowed.
+fn check_access(type_id: &TypeId, type_name: &'static str, write: bool) -> Result<(), EcsError> {
+    DECLARED.with_borrow(|declared| {
+        let Some((system, access)) = declared else {
+            return Ok(());
+        };
+        let allowed = if write {
+            access.allows_write(type_id)
+        } else {
+            access.allows_read(type_id)
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(EcsError::UndeclaredAccess { system, type_name })
+        }
+    })
+}
+
+/// A lock left poisoned by a panicking system still holds whole values, so
+/// later accesses take it over instead of failing.
+fn try_read(lock: &RwLock<Erased>) -> Option<RwLockReadGuard<'_, Erased>> {
+    match lock.try_read() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(error)) => Some(error.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+fn try_write(lock: &RwLock<Erased>) -> Option<RwLockWriteGuard<'_, Erased>> {
+    match lock.try_write() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(error)) => Some(error.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+/// One component type's storage: a `Vec<Option<(generation, T)>>` behind a
+/// lock. Storing the generation next to the value lets the column guards
+/// reject stale handles without going back to the world.
+#[derive(Debug)]
+struct Column {
+    data: RwLock<Erased>,
+    push_none: fn(&mut Erased),
+    clear: fn(&mut Erased, usize),
+}
+
+type Slots<T> = Vec<Option<(u32, T)>>;
+
+fn push_none<T: Any + Send + Sync>(data: &mut Erased) {
+    data.downcast_mut::<Slots<T>>().unwrap().push(None);
+}
+
+fn clear<T: Any + Send + Sync>(data: &mut Erased, index: usize) {
+    data.downcast_mut::<Slots<T>>().unwrap()[index] = None;
+}
+
+/// `Send + Sync` counterpart of `World`. Components are locked a whole
+/// column at a time, so systems that touch disjoint component and resource
+/// types can run on different threads; see `ParallelSchedule`.
+#[derive(Debug, Default)]
+pub struct SyncWorld {
+    columns: HashMap<TypeId, Column>,
+    resources: HashMap<TypeId, RwLock<Erased>>,
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free_indexes: Vec<usize>,
+    inserting_into_index: usize,
+}
+
+impl SyncWorld {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_resource(&mut self, resource_data: impl Any + Send + Sync) {
+        let type_id = resource_data.type_id();
+        self.resources
+            .insert(type_id, RwLock::new(Box::new(resource_data)));
+    }
+
+    pub fn resource<T: Any>(&self) -> Result<ReadResource<'_, T>, EcsError> {
+        let guard =
+            try_read(self.resource_lock::<T>(false)?).ok_or(EcsError::ResourceBorrowed {
+                type_name: short_type_name::<T>(),
+            })?;
+        Ok(ReadResource {
+            guard,
+            marker: PhantomData,
+        })
+    }
+
+    pub fn resource_mut<T: Any>(&self) -> Result<WriteResource<'_, T>, EcsError> {
+        let guard =
+            try_write(self.resource_lock::<T>(true)?).ok_or(EcsError::ResourceBorrowed {
+                type_name: short_type_name::<T>(),
+            })?;
+        Ok(WriteResource {
+            guard,
+            marker: PhantomData,
+        })
+    }
+
+    fn resource_lock<T: Any>(&self, write: bool) -> Result<&RwLock<Erased>, EcsError> {
+        let type_id = TypeId::of::<T>();
+        check_access(&type_id, short_type_name::<T>(), write)?;
+        self.resources
+            .get(&type_id)
+            .ok_or(EcsError::ResourceMissing {
+                type_name: short_type_name::<T>(),
+            })
+    }
+
+    pub fn register_component<T: Component + Send + Sync>(&mut self) {
+        let type_id = TypeId::of::<T>();
+        if self.columns.contains_key(&type_id) {
+            return;
+        }
+        let slots: Slots<T> = (0..self.alive.len()).map(|_| None).collect();
+        self.columns.insert(
+            type_id,
+            Column {
+                data: RwLock::new(Box::new(slots)),
+                push_none: push_none::<T>,
+                clear: clear::<T>,
+            },
+        );
+    }
+
+    pub fn create_entity(&mut self) -> &mut Self {
+        if let Some(index) = self.free_indexes.pop() {
+            self.alive[index] = true;
+            self.inserting_into_index = index;
+        } else {
+            self.columns.values_mut().for_each(|column| {
+                (column.push_none)(
+                    column
+                        .data
+                        .get_mut()
+                        .unwrap_or_else(PoisonError::into_inner),
+                )
+            });
+            self.generations.push(0);
+            self.alive.push(true);
+            self.inserting_into_index = self.alive.len() - 1;
+        }
+        self
+    }
+
+    pub fn with_component(
+        &mut self,
+        data: impl Component + Send + Sync,
+    ) -> Result<&mut Self, EcsError> {
+        if self.inserting_into_index >= self.alive.len() {
+            return Err(EcsError::NoEntityBeingCreated);
+        }
+        self.insert_component(self.current_entity(), data)?;
+        Ok(self)
+    }
+
+    pub fn current_entity(&self) -> Entity {
+        Entity {
+            index: self.inserting_into_index,
+            generation: self.generations[self.inserting_into_index],
+        }
+    }
+
+    pub fn insert_component<T: Component + Send + Sync>(
+        &mut self,
+        entity: Entity,
+        data: T,
+    ) -> Result<(), EcsError> {
+        if !self.is_alive(entity) {
+            return Err(EcsError::StaleEntity { entity });
+        }
+        let column =
+            self.columns
+                .get_mut(&TypeId::of::<T>())
+                .ok_or(EcsError::ComponentNotRegistered {
+                    type_name: T::TYPE_NAME,
+                })?;
+        let slots = column
+            .data
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        slots.downcast_mut::<Slots<T>>().unwrap()[entity.index] = Some((entity.generation, data));
+        Ok(())
+    }
+
+    pub fn despawn(&mut self, entity: Entity) -> Result<(), EcsError> {
+        if !self.is_alive(entity) {
+            return Err(EcsError::StaleEntity { entity });
+        }
+        let index = entity.index;
+        self.columns.values_mut().for_each(|column| {
+            (column.clear)(
+                column
+                    .data
+                    .get_mut()
+                    .unwrap_or_else(PoisonError::into_inner),
+                index,
+            )
+        });
+        self.alive[index] = false;
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.free_indexes.push(index);
+        Ok(())
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.alive.get(entity.index).copied().unwrap_or(false)
+            && self.generations[entity.index] == entity.generation
+    }
+
+    /// Read lock on every `T` in the world. Fails with `ComponentsBorrowed`
+    /// instead of blocking while the write lock is held.
+    pub fn read<T: Component>(&self) -> Result<ReadComponents<'_, T>, EcsError> {
+        let guard =
+            try_read(&self.column::<T>(false)?.data).ok_or(EcsError::ComponentsBorrowed {
+                type_name: T::TYPE_NAME,
+            })?;
+        Ok(ReadComponents {
+            guard,
+            marker: PhantomData,
+        })
+    }
+
+    /// Write lock on every `T` in the world. Fails with `ComponentsBorrowed`
+    /// instead of blocking while any other lock on `T` is held.
+    pub fn write<T: Component>(&self) -> Result<WriteComponents<'_, T>, EcsError> {
+        let guard =
+            try_write(&self.column::<T>(true)?.data).ok_or(EcsError::ComponentsBorrowed {
+                type_name: T::TYPE_NAME,
+            })?;
+        Ok(WriteComponents {
+            guard,
+            marker: PhantomData,
+        })
+    }
+
+    fn column<T: Component>(&self, write: bool) -> Result<&Column, EcsError> {
+        let type_id = TypeId::of::<T>();
+        check_access(&type_id, T::TYPE_NAME, write)?;
+        self.columns
+            .get(&type_id)
+            .ok_or(EcsError::ComponentNotRegistered {
+                type_name: T::TYPE_NAME,
+            })
+    }
+}
+
+fn get<T: Any>(slots: &Slots<T>, entity: Entity) -> Option<&T> {
+    match slots.get(entity.index)? {
+        Some((generation, data)) if *generation == entity.generation => Some(data),
+        _ => None,
+    }
+}
+
+fn iter<T: Any>(slots: &Slots<T>) -> impl Iterator<Item = (Entity, &T)> {
+    slots.iter().enumerate().filter_map(|(index, slot)| {
+        slot.as_ref().map(|(generation, data)| {
+            (
+                Entity {
+                    index,
+                    generation: *generation,
+                },
+                data,
+            )
+        })
+    })
+}
+
+pub struct ReadComponents<'a, T> {
+    guard: RwLockReadGuard<'a, Erased>,
+    marker: PhantomData<T>,
+}
+
+impl<T: Any> ReadComponents<'_, T> {
+    fn slots(&self) -> &Slots<T> {
+        self.guard.downcast_ref().unwrap()
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        get(self.slots(), entity)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        iter(self.slots())
+    }
+}
+
+pub struct WriteComponents<'a, T> {
+    guard: RwLockWriteGuard<'a, Erased>,
+    marker: PhantomData<T>,
+}
+
+impl<T: Any> WriteComponents<'_, T> {
+    fn slots(&self) -> &Slots<T> {
+        self.guard.downcast_ref().unwrap()
+    }
+
+    fn slots_mut(&mut self) -> &mut Slots<T> {
+        self.guard.downcast_mut().unwrap()
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        get(self.slots(), entity)
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        match self.slots_mut().get_mut(entity.index)? {
+            Some((generation, data)) if *generation == entity.generation => Some(data),
+            _ => None,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        iter(self.slots())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.slots_mut()
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                slot.as_mut().map(|(generation, data)| {
+                    (
+                        Entity {
+                            index,
+                            generation: *generation,
+                        },
+                        data,
+                    )
+                })
+            })
+    }
+}
+
+pub struct ReadResource<'a, T> {
+    guard: RwLockReadGuard<'a, Erased>,
+    marker: PhantomData<T>,
+}
+
+impl<T: Any> Deref for ReadResource<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.guard.downcast_ref().unwrap()
+    }
+}
+
+pub struct WriteResource<'a, T> {
+    guard: RwLockWriteGuard<'a, Erased>,
+    marker: PhantomData<T>,
+}
+
+impl<T: Any> Deref for WriteResource<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.guard.downcast_ref().unwrap()
+    }
+}
+
+impl<T: Any> DerefMut for WriteResource<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.guard.downcast_mut().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Component)]
+    struct Health(pub u32);
+    #[derive(Component)]
+    struct Speed(pub u32);
+    struct Turn(pub u32);
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn sync_world_is_send_and_sync() {
+        assert_send_sync::<SyncWorld>();
+    }
+
+    #[test]
+    fn read_and_write_columns() -> Result<(), EcsError> {
+        let mut world = SyncWorld::new();
+        world.register_component::<Health>();
+        world.register_component::<Speed>();
+        let first = world
+            .create_entity()
+            .with_component(Health(100))?
+            .with_component(Speed(15))?
+            .current_entity();
+        let second = world
+            .create_entity()
+            .with_component(Health(40))?
+            .current_entity();
+
+        {
+            let speeds = world.read::<Speed>().unwrap();
+            let mut healths = world.write::<Health>().unwrap();
+            for (entity, speed) in speeds.iter() {
+                healths.get_mut(entity).unwrap().0 += speed.0;
+            }
+        }
+
+        let healths = world.read::<Health>().unwrap();
+        assert_eq!(healths.get(first), Some(&Health(115)));
+        assert_eq!(healths.get(second), Some(&Health(40)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn stale_handles_are_rejected() -> Result<(), EcsError> {
+        let mut world = SyncWorld::new();
+        world.register_component::<Health>();
+        let first = world
+            .create_entity()
+            .with_component(Health(100))?
+            .current_entity();
+        world.despawn(first)?;
+        let second = world
+            .create_entity()
+            .with_component(Health(1))?
+            .current_entity();
+
+        assert_eq!(first.index, second.index);
+        let healths = world.read::<Health>().unwrap();
+        assert!(healths.get(first).is_none());
+        assert_eq!(healths.get(second), Some(&Health(1)));
+        assert_eq!(healths.iter().count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn conflicting_locks_are_errors() -> Result<(), EcsError> {
+        let mut world = SyncWorld::new();
+        world.register_component::<Health>();
+        world.add_resource(Turn(0));
+
+        let healths = world.read::<Health>()?;
+        assert!(world.read::<Health>().is_ok());
+        assert_eq!(
+            world.write::<Health>().err(),
+            Some(EcsError::ComponentsBorrowed {
+                type_name: Health::TYPE_NAME
+            })
+        );
+        drop(healths);
+        let turn = world.resource_mut::<Turn>()?;
+        assert!(matches!(
+            world.resource::<Turn>(),
+            Err(EcsError::ResourceBorrowed { .. })
+        ));
+        drop(turn);
+
+        Ok(())
+    }
+
+    #[test]
+    fn panics_dont_lock_out_later_access() -> Result<(), EcsError> {
+        let mut world = SyncWorld::new();
+        world.register_component::<Health>();
+        let entity = world
+            .create_entity()
+            .with_component(Health(1))?
+            .current_entity();
+
+        let panicked = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let _healths = world.write::<Health>().unwrap();
+                    panic!("system failed");
+                })
+                .join()
+                .is_err()
+        });
+
+        assert!(panicked);
+        assert_eq!(world.read::<Health>()?.get(entity), Some(&Health(1)));
+        world.despawn(entity)?;
+        Ok(())
+    }
+
+    #[test]
+    fn worlds_can_move_between_threads() -> Result<(), EcsError> {
+        let mut world = SyncWorld::new();
+        world.add_resource(Turn(0));
+
+        let world = std::thread::spawn(move || {
+            world.resource_mut::<Turn>().unwrap().0 += 1;
+            world
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(world.resource::<Turn>().unwrap().0, 1);
+        Ok(())
+    }
+}
@@ -1,13 +1,53 @@
-use std::any::Any;
+use std::{
+    any::{Any, TypeId},
+    cell::{Ref, RefMut},
+    collections::{HashMap, HashSet},
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
 
+use super::command::Commands;
+use super::component::{Component, Resource};
+use super::entity::bundle::{self, Bundle};
+use super::entity::entity_ref::{EntityBuilder, EntityMut, EntityRef};
 use super::entity::query::Query;
-use super::entity::Entities;
+use super::entity::storage::StorageKind;
+use super::entity::transfer::{EntityMappers, MapEntities};
+use super::entity::typed_query::{QueryData, TypedQuery};
+use super::entity::{Entities, Entity};
+use super::error::EcsError;
+use super::event::{EventReader, Events};
+use super::hook::{Hook, HookEvent, HookKind, Hooks};
+use super::inspect::{Describe, Describers};
+use super::persist::{Persist, Persisters, SaveError};
+use super::prefab::Prefabs;
 use super::resource::Resources;
+use super::schedule::{Schedule, Stage, System, SystemEntry};
+use super::snapshot::{Snapshot, Snapshotters};
 
 #[derive(Debug, Default)]
 pub struct World {
     entities: Entities,
     resources: Resources,
+    schedule: Schedule,
+    event_updaters: Vec<fn(&mut World)>,
+    commands: Commands,
+    persisters: Persisters,
+    prefabs: Prefabs,
+    hooks: Hooks,
+    snapshotters: Snapshotters,
+    describers: Describers,
+    entity_mappers: EntityMappers,
+    command_errors: Vec<EcsError>,
+    /// Components whose `on_remove` hooks are running, so a hook that
+    /// removes the same one again doesn't run them again.
+    removing: HashSet<(Entity, TypeId)>,
+}
+
+fn update_events<E: Any>(world: &mut World) {
+    if let Some(events) = world.get_resource_mut::<Events<E>>() {
+        events.update();
+    }
 }
 
 impl World {
@@ -19,7 +59,11 @@ impl World {
         self.resources.add(resource_data);
     }
 
-    pub fn get_resource<T: Any>(&self) -> Option<&T> {
+    /// The `T` resource, unless there is none or it is mutably borrowed.
+    /// Resources are borrow-checked at runtime, so this hands out a `Ref`
+    /// guard rather than a plain `&T`; drop it before borrowing `T`
+    /// mutably.
+    pub fn get_resource<T: Any>(&self) -> Option<Ref<'_, T>> {
         self.resources.get_ref::<T>()
     }
 
@@ -27,22 +71,533 @@ impl World {
         self.resources.get_mut::<T>()
     }
 
-    pub fn register_component<T: Any + 'static>(&mut self) {
+    /// Borrows the `T` resource, checked at runtime like
+    /// `EntityRef::get`.
+    pub fn resource<T: Any>(&self) -> Result<Ref<'_, T>, EcsError> {
+        self.resources.borrow::<T>()
+    }
+
+    /// Mutably borrows the `T` resource through `&World`, e.g. while a query
+    /// is alive.
+    pub fn resource_mut<T: Any>(&self) -> Result<RefMut<'_, T>, EcsError> {
+        self.resources.borrow_mut::<T>()
+    }
+
+    pub fn contains_resource<T: Any>(&self) -> bool {
+        self.resources.contains::<T>()
+    }
+
+    pub fn remove_resource<T: Any>(&mut self) -> Option<T> {
+        self.resources.remove::<T>()
+    }
+
+    /// The `T` resource, added first with `insert` if there is none.
+    pub fn get_or_insert_resource_with<T: Any>(&mut self, insert: impl FnOnce() -> T) -> &mut T {
+        self.resources.get_or_insert_with(insert)
+    }
+
+    pub fn register_component<T: Component>(&mut self) {
         self.entities.register_component::<T>();
     }
 
-    pub fn create_entity(&mut self) -> &mut Entities {
-        self.entities.create_entity()
+    pub fn register_component_with<T: Component>(&mut self, kind: StorageKind) {
+        self.entities.register_component_with::<T>(kind);
+    }
+
+    /// Registers `T` with the storage, saving and inspection its
+    /// `#[derive(Component)]` attributes ask for.
+    pub fn register<T: Component>(&mut self) -> Result<(), SaveError> {
+        T::register(self)
+    }
+
+    /// Sets up the saving and inspection the `#[derive(Resource)]`
+    /// attributes of `T` ask for. The resource itself is still added with
+    /// `add_resource`.
+    pub fn register_resource<T: Resource>(&mut self) -> Result<(), SaveError> {
+        T::register(self)
+    }
+
+    /// Registers `T` and includes it in `save`. Fails with `DuplicateName`
+    /// if another saved component has the same `Persist::NAME`.
+    pub fn register_saved_component<T: Persist + Component>(&mut self) -> Result<(), SaveError> {
+        self.persisters.add_component::<T>()?;
+        self.register_component::<T>();
+        Ok(())
+    }
+
+    /// Includes the `T` resource in `save`, if there is one. Fails with
+    /// `DuplicateName` if another saved resource has the same
+    /// `Persist::NAME`.
+    pub fn register_saved_resource<T: Persist>(&mut self) -> Result<(), SaveError> {
+        self.persisters.add_resource::<T>()
+    }
+
+    /// Writes every entity slot plus the saved components and resources; see
+    /// `persist` for the format.
+    pub fn save(&self, out: &mut impl Write) -> Result<(), SaveError> {
+        self.persisters.save(self, out)
+    }
+
+    /// Restores a save into this world, which needs the same registrations
+    /// as the one that saved it and no entities yet. If the save can't be
+    /// read the world is left as it was. `on_add` hooks run once everything
+    /// is in.
+    pub fn load(&mut self, input: impl BufRead) -> Result<(), SaveError> {
+        if self.entities.slots().next().is_some() {
+            return Err(SaveError::WorldNotEmpty);
+        }
+        let empty = self
+            .entities
+            .clone_with(&HashMap::new())
+            .expect("there are no components to borrow");
+        let persisters = self.persisters.clone();
+        match persisters.load(self, input) {
+            Ok(resources) => {
+                self.resources.append(resources);
+                self.run_hooks();
+                Ok(())
+            }
+            Err(err) => {
+                self.entities = empty;
+                Err(err)
+            }
+        }
+    }
+
+    /// Registers `T` and includes it in `snapshot`.
+    pub fn register_snapshot_component<T: Clone + Component>(&mut self) {
+        self.register_component::<T>();
+        self.snapshotters.add_component::<T>();
+    }
+
+    /// Includes the `T` resource in `snapshot`, if there is one.
+    pub fn register_snapshot_resource<T: Clone + Any>(&mut self) {
+        self.snapshotters.add_resource::<T>();
+    }
+
+    /// Copies every entity slot plus the snapshot components and resources.
+    /// Fails if one of them is mutably borrowed.
+    pub fn snapshot(&self) -> Result<Snapshot, EcsError> {
+        self.snapshotters.snapshot(self, &self.entities)
+    }
+
+    /// Reverts entities and snapshot resources to `snapshot`. Components
+    /// that aren't registered with `register_snapshot_component` are gone
+    /// afterwards. Hooks don't run.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.entities
+            .restore(&snapshot.entities, &self.snapshotters.components);
+        let snapshotters = self.snapshotters.clone();
+        snapshotters.restore_resources(self, snapshot);
+    }
+
+    /// Makes `dump` show the value of `T` components or of the `T` resource.
+    pub fn register_described<T: Describe>(&mut self) {
+        self.describers.add::<T>();
+    }
+
+    /// Writes every live entity and resource with their type names, and
+    /// values where described; see `inspect` for the format.
+    pub fn dump(&self, out: &mut impl Write) -> io::Result<()> {
+        self.describers.dump(&self.entities, &self.resources, out)
+    }
+
+    /// Adds the prefabs described in `input`; see `prefab` for the format.
+    /// Their components have to be registered with
+    /// `register_saved_component` first.
+    pub fn load_prefabs(&mut self, input: impl BufRead) -> Result<(), SaveError> {
+        self.prefabs.load(&self.persisters, input)
+    }
+
+    pub fn has_prefab(&self, name: &str) -> bool {
+        self.prefabs.contains(name)
+    }
+
+    /// Spawns the named prefab plus `bundle`, e.g. its position. Components
+    /// in `bundle` are added instead of the prefab's own, so each type is
+    /// added once. Hooks run once all of them are in. If a component can't
+    /// be inserted, the entity is despawned again rather than left
+    /// half-built.
+    pub fn spawn_prefab<B: Bundle>(&mut self, name: &str, bundle: B) -> Result<Entity, EcsError> {
+        if !self.has_prefab(name) {
+            return Err(EcsError::UnknownPrefab {
+                name: name.to_string(),
+            });
+        }
+        bundle::check::<B>()?;
+        let entity = self.create_entity().current_entity();
+        let prefabs = std::mem::take(&mut self.prefabs);
+        let inserted = prefabs.insert(name, self, entity, &B::type_ids());
+        self.prefabs = prefabs;
+        let inserted = inserted.and_then(|()| self.entities.insert_bundle(entity, bundle));
+        self.run_hooks();
+        // A hook may have despawned it already.
+        if inserted.is_err() && self.is_alive(entity) {
+            self.despawn(entity)?;
+        }
+        inserted.map(|_| entity)
+    }
+
+    pub(crate) fn slots(&self) -> impl Iterator<Item = (Entity, bool)> + '_ {
+        self.entities.slots()
+    }
+
+    pub(crate) fn push_slot(&mut self, generation: u32, sequence: Option<u64>) -> Entity {
+        self.entities.push_slot(generation, sequence)
+    }
+
+    pub(crate) fn free_slots(&self) -> impl Iterator<Item = usize> + '_ {
+        self.entities.free_slots()
+    }
+
+    pub(crate) fn set_free_slots(&mut self, order: &[usize]) -> bool {
+        self.entities.set_free_slots(order)
+    }
+
+    pub(crate) fn spawn_sequence(&self, entity: Entity) -> Option<u64> {
+        self.entities.spawn_sequence(entity)
+    }
+
+    pub(crate) fn spawn_count(&self) -> u64 {
+        self.entities.spawn_count()
+    }
+
+    pub(crate) fn set_spawn_count(&mut self, count: u64) {
+        self.entities.set_spawn_count(count);
+    }
+
+    pub fn storage_kind<T: Component>(&self) -> Option<StorageKind> {
+        self.entities.storage_kind::<T>()
+    }
+
+    /// Creates an empty entity; add components with `with_component`.
+    pub fn create_entity(&mut self) -> EntityBuilder<'_> {
+        let entity = self.entities.create_entity().current_entity();
+        EntityBuilder::new(entity, self)
+    }
+
+    /// Spawns an entity with every component in `bundle`; see
+    /// `Entities::spawn`.
+    pub fn spawn<B: Bundle>(&mut self, bundle: B) -> Result<Entity, EcsError> {
+        let spawned = self.entities.spawn(bundle);
+        self.run_hooks();
+        spawned
+    }
+
+    pub fn insert_component<T: Component>(
+        &mut self,
+        entity: Entity,
+        data: T,
+    ) -> Result<(), EcsError> {
+        let inserted = self.entities.insert_component(entity, data);
+        self.run_hooks();
+        inserted
+    }
+
+    /// `insert_component` that leaves the hooks to whoever inserts the rest
+    /// of the entity, e.g. `load`.
+    pub(super) fn insert_unhooked<T: Component>(
+        &mut self,
+        entity: Entity,
+        data: T,
+    ) -> Result<(), EcsError> {
+        self.entities.insert_component(entity, data)
+    }
+
+    /// Takes the component off the entity; see `Entities::remove_component`.
+    pub fn remove_component<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        if !self.check_remove::<T>(entity).unwrap_or(false) {
+            return None;
+        }
+        self.run_remove_hooks(entity, vec![TypeId::of::<T>()]);
+        if !self.entities.is_alive(entity) {
+            // A hook despawned it, taking the component along.
+            return None;
+        }
+        self.entities.remove_component::<T>(entity)
+    }
+
+    /// Whether `remove_component` would take a `T` off `entity`, or why it
+    /// can't.
+    pub(super) fn check_remove<T: Component>(&self, entity: Entity) -> Result<bool, EcsError> {
+        self.entities.check_remove::<T>(entity)
+    }
+
+    pub fn has_component<T: Component>(&self, entity: Entity) -> bool {
+        self.entities.has_component::<T>(entity)
+    }
+
+    /// Despawns `entity` and all of its descendants.
+    pub fn despawn(&mut self, entity: Entity) -> Result<(), EcsError> {
+        if !self.is_alive(entity) {
+            return Err(EcsError::StaleEntity { entity });
+        }
+        self.run_despawn_hooks(entity);
+        if !self.is_alive(entity) {
+            // A hook despawned it already.
+            return Ok(());
+        }
+        self.entities.despawn(entity)
+    }
+
+    /// Calls `hook` whenever a `T` is inserted on an entity that didn't have
+    /// one, right after the insert.
+    pub fn on_add<T: Component>(&mut self, hook: impl Fn(&mut World, Entity) + 'static) {
+        self.add_hook::<T>(HookKind::Add, Rc::new(hook));
+    }
+
+    /// Calls `hook` whenever a `T` is inserted over an existing one.
+    pub fn on_replace<T: Component>(&mut self, hook: impl Fn(&mut World, Entity) + 'static) {
+        self.add_hook::<T>(HookKind::Replace, Rc::new(hook));
+    }
+
+    /// Calls `hook` right before a `T` is removed, including by a despawn,
+    /// so it can still read the value. A hook that removes the same `T`
+    /// again, e.g. by despawning its entity, doesn't trigger itself.
+    pub fn on_remove<T: Component>(&mut self, hook: impl Fn(&mut World, Entity) + 'static) {
+        self.add_hook::<T>(HookKind::Remove, Rc::new(hook));
+    }
+
+    fn add_hook<T: Component>(&mut self, kind: HookKind, hook: Hook) {
+        self.entities.watch(TypeId::of::<T>());
+        self.hooks.add(TypeId::of::<T>(), kind, hook);
+    }
+
+    /// Runs the add and replace hooks for everything recorded since the last
+    /// call, including changes the hooks themselves make.
+    pub(super) fn run_hooks(&mut self) {
+        for event in self.entities.take_hook_events() {
+            for hook in self.hooks.get(&event) {
+                hook(self, event.entity);
+            }
+        }
+    }
+
+    /// Runs the `on_remove` hooks of the `type_ids` components `entity`
+    /// still has.
+    fn run_remove_hooks(&mut self, entity: Entity, type_ids: Vec<TypeId>) {
+        for type_id in type_ids {
+            let event = HookEvent {
+                kind: HookKind::Remove,
+                type_id,
+                entity,
+            };
+            let hooks = self.hooks.get(&event);
+            if hooks.is_empty()
+                || !self.entities.contains_type(entity, type_id)
+                || !self.removing.insert((entity, type_id))
+            {
+                continue;
+            }
+            for hook in hooks {
+                hook(self, entity);
+            }
+            self.removing.remove(&(entity, type_id));
+        }
+    }
+
+    /// Runs the `on_remove` hooks for every component of `entity` and its
+    /// descendants, in the order `despawn` removes them.
+    fn run_despawn_hooks(&mut self, entity: Entity) {
+        let mut doomed = self.entities.descendants(entity);
+        doomed.push(entity);
+        for doomed in doomed {
+            let type_ids = self
+                .entities
+                .components_of(doomed)
+                .into_iter()
+                .map(|(type_id, _, _)| type_id)
+                .collect();
+            self.run_remove_hooks(doomed, type_ids);
+        }
+    }
+
+    /// Lets `transfer_entity` point the `Entity` handles in `T` components
+    /// at the moved entities.
+    pub fn register_entity_refs<T: MapEntities>(&mut self) {
+        self.entity_mappers.add::<T>();
+    }
+
+    /// Moves `entity`, its components and its descendants into `other`,
+    /// e.g. the player and their inventory taking the stairs. Component
+    /// types missing in `other` are registered there, along with how this
+    /// world saves, snapshots, describes and remaps them. Returns the
+    /// entity's handle in `other`; the old handles go stale. `on_remove`
+    /// hooks run here and `on_add` hooks in `other`, after handles are
+    /// remapped. Fails before anything moves if a moved component is still
+    /// shared by a `Query::run` result.
+    pub fn transfer_entity(
+        &mut self,
+        entity: Entity,
+        other: &mut World,
+    ) -> Result<Entity, EcsError> {
+        self.entities.check_take(entity)?;
+        self.run_despawn_hooks(entity);
+        let taken = self.entities.take(entity)?;
+        let type_ids: HashSet<TypeId> = taken.iter().flat_map(|taken| taken.type_ids()).collect();
+        for type_id in type_ids {
+            other.persisters.copy_component(&self.persisters, type_id);
+            other
+                .snapshotters
+                .copy_component(&self.snapshotters, type_id);
+            other.describers.copy(&self.describers, type_id);
+            other.entity_mappers.copy(&self.entity_mappers, type_id);
+        }
+        let map = other.entities.put(taken);
+        other.entity_mappers.apply(&other.entities, &map);
+        other.run_hooks();
+        Ok(map.get(entity).unwrap())
+    }
+
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) -> Result<(), EcsError> {
+        self.entities.set_parent(child, parent)
+    }
+
+    pub fn remove_parent(&mut self, child: Entity) -> Option<Entity> {
+        self.entities.remove_parent(child)
+    }
+
+    pub fn parent(&self, child: Entity) -> Option<Entity> {
+        self.entities.parent(child)
+    }
+
+    pub fn children(&self, parent: Entity) -> &[Entity] {
+        self.entities.children(parent)
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.entities.is_alive(entity)
+    }
+
+    pub fn entity(&self, entity: Entity) -> Result<EntityRef<'_>, EcsError> {
+        EntityRef::new(entity, &self.entities)
+    }
+
+    pub fn entity_mut(&mut self, entity: Entity) -> Result<EntityMut<'_>, EcsError> {
+        EntityMut::new(entity, self)
+    }
+
+    /// Entities that lost a `T` since the last `clear_trackers`.
+    pub fn removed<T: Component>(&self) -> &[Entity] {
+        self.entities.removed::<T>()
+    }
+
+    /// Starts a new change detection tick: `added` and `changed` filters
+    /// forget everything that happened so far, and so does `removed`.
+    /// `run_schedule` calls this after every run.
+    pub fn clear_trackers(&mut self) {
+        self.entities.clear_trackers();
+    }
+
+    /// Buffer for spawns, despawns, inserts and removals that can't happen
+    /// right away because something is borrowing the world.
+    pub fn commands(&self) -> &Commands {
+        &self.commands
+    }
+
+    /// Applies every recorded command in order. All of them are applied even
+    /// if some fail, e.g. despawning an entity twice; the first error is
+    /// returned.
+    pub fn apply_commands(&mut self) -> Result<(), EcsError> {
+        match self.apply_all_commands().into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Like `apply_commands`, but keeps every error for `command_errors`.
+    /// `Schedule::run` calls this after every system, so a failed command
+    /// doesn't stop the systems after it.
+    pub(super) fn apply_commands_logged(&mut self) {
+        let errors = self.apply_all_commands();
+        self.command_errors.extend(errors);
+    }
+
+    fn apply_all_commands(&mut self) -> Vec<EcsError> {
+        self.commands
+            .take()
+            .into_iter()
+            .filter_map(|command| command(self).err())
+            .collect()
+    }
+
+    /// Errors of the commands that failed during the last `run_schedule`,
+    /// oldest first.
+    pub fn command_errors(&self) -> &[EcsError] {
+        &self.command_errors
+    }
+
+    /// Adds an `Events<E>` resource that `run_schedule` updates after every
+    /// run. Does nothing if `E` was already added.
+    pub fn add_event<E: Any>(&mut self) {
+        if !self.contains_resource::<Events<E>>() {
+            self.add_resource(Events::<E>::default());
+            self.event_updaters.push(update_events::<E>);
+        }
+    }
+
+    pub fn send_event<E: Any>(&mut self, event: E) {
+        self.add_event::<E>();
+        self.get_resource_mut::<Events<E>>().unwrap().send(event);
+    }
+
+    /// Events `reader` hasn't seen yet. Empty if no `E` was ever sent, or
+    /// if the `Events<E>` resource is mutably borrowed.
+    pub fn read_events<'a, E: Any>(
+        &'a self,
+        reader: &mut EventReader<E>,
+    ) -> impl Iterator<Item = Ref<'a, E>> + use<'a, E> {
+        let events = self.get_resource::<Events<E>>();
+        let unread = events
+            .as_ref()
+            .map(|events| reader.unread(events))
+            .unwrap_or_default();
+        unread
+            .map(|position| {
+                let events = Ref::clone(events.as_ref().unwrap());
+                Ref::map(events, |events| events.get(position))
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    pub fn add_system(
+        &mut self,
+        stage: Stage,
+        name: &'static str,
+        system: impl System + 'static,
+    ) -> &mut SystemEntry {
+        self.schedule.add_system(stage, name, system)
+    }
+
+    pub fn run_schedule(&mut self) -> Result<(), EcsError> {
+        self.command_errors.clear();
+        let mut schedule = std::mem::take(&mut self.schedule);
+        let result = schedule.run(self);
+        // Keep anything a system added to the schedule while it was running.
+        schedule.append(std::mem::take(&mut self.schedule));
+        self.schedule = schedule;
+        for update in self.event_updaters.clone() {
+            update(self);
+        }
+        self.clear_trackers();
+        result
+    }
+
+    pub fn query<Q: QueryData>(&self) -> TypedQuery<'_, Q> {
+        TypedQuery::new(&self.entities)
     }
 
-    pub fn query(&self) -> Query {
+    pub fn dynamic_query(&self) -> Query<'_> {
         Query::new(&self.entities)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use std::{cell::RefCell, rc::Rc};
+    use std::rc::Rc;
+
+    use crate::ecs::{entity::ComponentCell, Component};
 
     use super::*;
 
@@ -65,7 +620,40 @@ mod test {
     }
 
     #[test]
-    fn create_entity() -> Result<(), &'static str> {
+    fn resources_borrow_alongside_queries() -> Result<(), EcsError> {
+        let mut world = initialize_world();
+        world.spawn((Size(2.0),))?;
+        for size in world.query::<&Size>().iter()? {
+            let size = size?;
+            world.resource_mut::<FpsResource>()?.0 += size.0 as u32;
+        }
+        assert_eq!(world.resource::<FpsResource>()?.0, 62);
+
+        let fps = world.resource_mut::<FpsResource>()?;
+        assert!(matches!(
+            world.resource::<FpsResource>(),
+            Err(EcsError::ResourceBorrowed { .. })
+        ));
+        drop(fps);
+
+        assert_eq!(*world.get_or_insert_resource_with(|| 7u8), 7);
+        assert!(world.contains_resource::<u8>());
+        assert_eq!(
+            world.remove_resource::<FpsResource>().map(|fps| fps.0),
+            Some(62)
+        );
+        assert_eq!(
+            world.resource::<FpsResource>().err(),
+            Some(EcsError::ResourceMissing {
+                type_name: "FpsResource"
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn create_entity() -> Result<(), EcsError> {
         let mut world = World::new();
         world.register_component::<Location>();
         world.register_component::<Size>();
@@ -84,7 +672,7 @@ mod test {
     }
 
     #[test]
-    fn query_for_entities() -> Result<(), &'static str> {
+    fn query_for_entities() -> Result<(), EcsError> {
         let mut world = World::new();
         world.register_component::<Location>();
         world.register_component::<Size>();
@@ -104,13 +692,13 @@ mod test {
             .with_component(Size(12.0))?;
 
         let query = world
-            .query()
+            .dynamic_query()
             .with_component::<Location>()?
             .with_component::<Size>()?
-            .run();
+            .run()?;
 
-        let locations: &Vec<Rc<RefCell<dyn Any>>> = &query.1[0];
-        let sizes: &Vec<Rc<RefCell<dyn Any>>> = &query.1[1];
+        let locations: &Vec<Rc<dyn ComponentCell>> = &query.1[0];
+        let sizes: &Vec<Rc<dyn ComponentCell>> = &query.1[1];
 
         assert_eq!(locations.len(), sizes.len());
         assert_eq!(locations.len(), 2);
@@ -135,10 +723,428 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn despawned_entities_leave_queries() -> Result<(), EcsError> {
+        let mut world = World::new();
+        world.register_component::<Location>();
+
+        let first = world
+            .create_entity()
+            .with_component(Location(1.0, 1.0))?
+            .current_entity();
+        world.create_entity().with_component(Location(2.0, 2.0))?;
+
+        world.despawn(first)?;
+
+        let mut query = world.dynamic_query();
+        let query_entities = query.with_component::<Location>()?.run_query();
+        assert_eq!(query_entities.len(), 1);
+        assert_eq!(query_entities[0].get_component::<Location>()?.0, 2.0);
+        assert!(!world.is_alive(first));
+
+        Ok(())
+    }
+
+    #[test]
+    fn status_effects_come_and_go() -> Result<(), EcsError> {
+        let mut world = World::new();
+        world.register_component::<Location>();
+        world.register_component::<Size>();
+        let entity = world
+            .create_entity()
+            .with_component(Location(1.0, 1.0))?
+            .current_entity();
+
+        world.insert_component(entity, Size(3.0))?;
+        assert!(world.has_component::<Size>(entity));
+        let mut query = world.dynamic_query();
+        assert_eq!(query.with_component::<Size>()?.run_query().len(), 1);
+
+        let size = world.remove_component::<Size>(entity).unwrap();
+        assert_eq!(size.0, 3.0);
+        assert!(!world.has_component::<Size>(entity));
+        let mut query = world.dynamic_query();
+        assert_eq!(query.with_component::<Size>()?.run_query().len(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn typed_query_for_entities() -> Result<(), EcsError> {
+        let mut world = World::new();
+        world.register_component::<Location>();
+        world.register_component::<Size>();
+
+        world
+            .create_entity()
+            .with_component(Location(42.0, 24.0))?
+            .with_component(Size(10.0))?;
+        world.create_entity().with_component(Location(43.0, 25.0))?;
+
+        for item in world.query::<(&Location, &mut Size)>().iter()? {
+            let (location, mut size) = item?;
+            size.0 += location.0;
+        }
+
+        let sizes: Vec<f32> = world
+            .query::<&Size>()
+            .iter()?
+            .map(|item| item.map(|size| size.0))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(sizes, vec![52.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn run_schedule_clears_change_trackers() -> Result<(), EcsError> {
+        let mut world = World::new();
+        world.register_component::<Location>();
+        let entity = world
+            .create_entity()
+            .with_component(Location(42.0, 24.0))?
+            .current_entity();
+        world.add_system(Stage::Ai, "wander", |world: &mut World| {
+            for location in world.query::<&mut Location>().iter().unwrap() {
+                location.unwrap().0 += 1.0;
+            }
+        });
+        assert_eq!(
+            world
+                .query::<&Location>()
+                .added::<Location>()
+                .iter()?
+                .count(),
+            1
+        );
+
+        world.run_schedule()?;
+        assert_eq!(
+            world
+                .query::<&Location>()
+                .added::<Location>()
+                .iter()?
+                .count(),
+            0
+        );
+        assert_eq!(
+            world
+                .query::<&Location>()
+                .changed::<Location>()
+                .iter()?
+                .count(),
+            0
+        );
+
+        world.remove_component::<Location>(entity);
+        assert_eq!(world.removed::<Location>(), &[entity]);
+        world.run_schedule()?;
+        assert!(world.removed::<Location>().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn events_last_one_schedule_run() -> Result<(), EcsError> {
+        #[derive(Debug, PartialEq)]
+        struct Bumped(pub u32);
+
+        let mut world = World::new();
+        let mut reader = EventReader::<Bumped>::new();
+        assert_eq!(world.read_events(&mut reader).count(), 0);
+
+        world.send_event(Bumped(1));
+        world.run_schedule()?;
+        world.send_event(Bumped(2));
+        let bumps: Vec<u32> = world.read_events(&mut reader).map(|bump| bump.0).collect();
+        assert_eq!(bumps, [1, 2]);
+
+        world.run_schedule()?;
+        world.run_schedule()?;
+        let mut late = EventReader::<Bumped>::new();
+        assert_eq!(world.read_events(&mut late).count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn commands_apply_after_queries() -> Result<(), EcsError> {
+        let mut world = World::new();
+        world.register_component::<Location>();
+        world.register_component::<Size>();
+        let first = world
+            .create_entity()
+            .with_component(Location(1.0, 1.0))?
+            .with_component(Size(0.0))?
+            .current_entity();
+        world.create_entity().with_component(Location(2.0, 2.0))?;
+
+        for item in world.query::<(Entity, &Location)>().iter()? {
+            let (entity, location) = item?;
+            if location.0 == 1.0 {
+                world.commands().despawn(entity);
+                world.commands().despawn(entity);
+                world
+                    .commands()
+                    .spawn()
+                    .with_component(Size(5.0))
+                    .with_component(Location(location.0, location.1));
+            } else {
+                world.commands().insert(entity, Size(3.0));
+                world.commands().remove::<Location>(entity);
+            }
+        }
+        assert_eq!(world.commands().len(), 5);
+        assert!(world.is_alive(first));
+
+        assert_eq!(
+            world.apply_commands(),
+            Err(EcsError::StaleEntity { entity: first })
+        );
+        assert!(world.commands().is_empty());
+        assert!(!world.is_alive(first));
+        // The spawned entity reuses the first slot but is the newest.
+        let sizes: Vec<f32> = world
+            .query::<&Size>()
+            .iter()?
+            .map(|item| item.map(|size| size.0))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(sizes, vec![3.0, 5.0]);
+        assert_eq!(world.query::<&Location>().iter()?.count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_spawn_command_leaves_no_entity() -> Result<(), EcsError> {
+        let mut world = World::new();
+        world.register_component::<Location>();
+        world
+            .commands()
+            .spawn()
+            .with_component(Location(1.0, 1.0))
+            .with_component(Size(2.0));
+
+        assert_eq!(
+            world.apply_commands(),
+            Err(EcsError::ComponentNotRegistered {
+                type_name: Size::TYPE_NAME
+            })
+        );
+        assert_eq!(world.query::<Entity>().iter()?.count(), 0);
+        assert_eq!(world.removed::<Location>().len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_remove_commands_are_reported() -> Result<(), EcsError> {
+        let mut world = World::new();
+        let entity = world.spawn((Location(1.0, 1.0),))?;
+
+        world.commands().remove::<Size>(entity);
+        assert_eq!(
+            world.apply_commands(),
+            Err(EcsError::ComponentMissing {
+                entity,
+                type_name: Size::TYPE_NAME
+            })
+        );
+
+        let mut query = world.dynamic_query();
+        query.with_component::<Location>()?;
+        let (_, shared, _) = query.run()?;
+        world.commands().remove::<Location>(entity);
+        assert_eq!(
+            world.apply_commands(),
+            Err(EcsError::AlreadyBorrowed {
+                entity,
+                type_name: Location::TYPE_NAME
+            })
+        );
+        assert!(world.has_component::<Location>(entity));
+
+        drop(shared);
+        world.commands().remove::<Location>(entity);
+        world.apply_commands()?;
+        assert!(!world.has_component::<Location>(entity));
+
+        Ok(())
+    }
+
+    #[test]
+    fn spawn_bundles() -> Result<(), EcsError> {
+        let mut world = World::new();
+        let entity = world.spawn((Location(1.0, 2.0), Size(3.0)))?;
+        world.commands().spawn_bundle((Size(4.0),));
+        world.apply_commands()?;
+
+        assert!(world.has_component::<Location>(entity));
+        let sizes: Vec<f32> = world
+            .query::<&Size>()
+            .iter()?
+            .map(|item| item.map(|size| size.0))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(sizes, vec![3.0, 4.0]);
+
+        Ok(())
+    }
+
+    #[derive(Debug, Default)]
+    struct HookLog(Vec<(HookKind, Entity)>);
+
+    fn log(kind: HookKind) -> impl Fn(&mut World, Entity) {
+        move |world, entity| {
+            if world.get_resource::<HookLog>().is_none() {
+                world.add_resource(HookLog::default());
+            }
+            world
+                .get_resource_mut::<HookLog>()
+                .unwrap()
+                .0
+                .push((kind, entity));
+        }
+    }
+
+    #[test]
+    fn hooks_fire_on_add_replace_and_remove() -> Result<(), EcsError> {
+        let mut world = World::new();
+        world.register_component::<Location>();
+        world.register_component::<Size>();
+        world.on_add::<Location>(|world, entity| {
+            log(HookKind::Add)(world, entity);
+            // Hooks can change the world, and trigger other hooks.
+            world.insert_component(entity, Size(1.0)).unwrap();
+        });
+        world.on_replace::<Location>(|world, entity| log(HookKind::Replace)(world, entity));
+        world.on_remove::<Location>(|world, entity| log(HookKind::Remove)(world, entity));
+        world.on_remove::<Size>(|world, _| world.commands().spawn_bundle((Size(0.0),)));
+
+        let first = world.spawn((Location(1.0, 1.0),))?;
+        assert!(world.has_component::<Size>(first));
+        world.insert_component(first, Location(2.0, 2.0))?;
+        let second = world.spawn((Location(3.0, 3.0),))?;
+        world.remove_component::<Location>(second);
+        world.despawn(first)?;
+        let third = world
+            .create_entity()
+            .with_component(Location(4.0, 4.0))?
+            .current_entity();
+        let fourth = world.create_entity().current_entity();
+        world.entity_mut(fourth)?.insert(Location(5.0, 5.0))?;
+        assert_eq!(world.get_resource::<HookLog>().unwrap().0.len(), 7);
+        world.apply_commands()?;
+
+        assert_eq!(
+            world.get_resource::<HookLog>().unwrap().0,
+            vec![
+                (HookKind::Add, first),
+                (HookKind::Replace, first),
+                (HookKind::Add, second),
+                (HookKind::Remove, second),
+                (HookKind::Remove, first),
+                (HookKind::Add, third),
+                (HookKind::Add, fourth),
+            ]
+        );
+        // The on_remove hook for the despawned `Size` spawned a new one.
+        assert_eq!(world.query::<&Size>().iter()?.count(), 4);
+
+        Ok(())
+    }
+
+    #[derive(Debug, Default)]
+    struct RemovedSizes(Vec<f32>);
+
+    #[test]
+    fn on_remove_hooks_see_the_value() -> Result<(), EcsError> {
+        let mut world = World::new();
+        world.add_resource(RemovedSizes::default());
+        world.on_remove::<Size>(|world, entity| {
+            let size = world.entity(entity).unwrap().get::<Size>().unwrap().0;
+            world.resource_mut::<RemovedSizes>().unwrap().0.push(size);
+        });
+        // Despawning from the hook doesn't run it again for the same `Size`.
+        world.on_remove::<Location>(|world, entity| world.despawn(entity).unwrap());
+
+        let bag = world.spawn((Size(1.0),))?;
+        let coin = world.spawn((Size(2.0),))?;
+        world.set_parent(coin, bag)?;
+        assert_eq!(
+            world.remove_component::<Size>(coin).map(|size| size.0),
+            Some(2.0)
+        );
+        world.insert_component(coin, Size(3.0))?;
+        world.despawn(bag)?;
+        assert!(!world.is_alive(coin));
+
+        let trap = world.spawn((Size(4.0), Location(0.0, 0.0)))?;
+        assert!(world.remove_component::<Location>(trap).is_none());
+        assert!(!world.is_alive(trap));
+
+        assert_eq!(
+            world.resource::<RemovedSizes>()?.0,
+            vec![2.0, 3.0, 1.0, 4.0]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn hooks_can_capture_state() -> Result<(), EcsError> {
+        let mut world = World::new();
+        // Stand-in for a spatial index kept outside the world.
+        let index = Rc::new(std::cell::RefCell::new(HashMap::new()));
+        let added = index.clone();
+        world.on_add::<Location>(move |world, entity| {
+            let location = world.entity(entity).unwrap().get::<Location>().unwrap().0;
+            added.borrow_mut().insert(entity, location);
+        });
+        let removed = index.clone();
+        world.on_remove::<Location>(move |_, entity| {
+            removed.borrow_mut().remove(&entity);
+        });
+
+        let first = world.spawn((Location(1.0, 1.0),))?;
+        let second = world.spawn((Location(2.0, 2.0),))?;
+        world.despawn(first)?;
+
+        assert_eq!(*index.borrow(), HashMap::from([(second, 2.0)]));
+        Ok(())
+    }
+
+    /// `Size` of each entity when its `Location` was added.
+    #[derive(Debug, Default)]
+    struct AddedSizes(Vec<f32>);
+
+    #[test]
+    fn spawn_commands_run_hooks_on_the_whole_entity() -> Result<(), EcsError> {
+        let mut world = World::new();
+        world.register_component::<Location>();
+        world.register_component::<Size>();
+        world.add_resource(AddedSizes::default());
+        world.on_add::<Location>(|world, entity| {
+            let size = world.entity(entity).unwrap().get::<Size>().unwrap().0;
+            world.resource_mut::<AddedSizes>().unwrap().0.push(size);
+        });
+
+        world
+            .commands()
+            .spawn()
+            .with_component(Location(0.0, 0.0))
+            .with_component(Size(5.0));
+        world.apply_commands()?;
+
+        assert_eq!(world.resource::<AddedSizes>()?.0, vec![5.0]);
+        Ok(())
+    }
+
     #[derive(Debug)]
     struct FpsResource(pub u32);
 
+    #[derive(Component)]
     struct Location(pub f32, pub f32);
+    #[derive(Component)]
     struct Size(pub f32);
 
     impl std::ops::Deref for FpsResource {
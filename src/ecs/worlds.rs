@@ -0,0 +1,145 @@
+use super::{EcsError, Entity, World};
+
+/// Several worlds of which one is active, e.g. one per dungeon level.
+/// Inactive worlds are frozen: only the active one's schedule runs, so the
+/// others stay exactly as they were left until they are activated again.
+#[derive(Debug)]
+pub struct Worlds {
+    worlds: Vec<World>,
+    active: usize,
+}
+
+impl Worlds {
+    /// Starts with `world`, as world 0, active.
+    pub fn new(world: World) -> Self {
+        Self {
+            worlds: vec![world],
+            active: 0,
+        }
+    }
+
+    /// Adds a frozen world and returns its id.
+    pub fn add(&mut self, world: World) -> usize {
+        self.worlds.push(world);
+        self.worlds.len() - 1
+    }
+
+    /// Number of worlds, which is at least 1: there is always an active
+    /// one, so there's no `is_empty`.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.worlds.len()
+    }
+
+    pub fn active_id(&self) -> usize {
+        self.active
+    }
+
+    pub fn active(&self) -> &World {
+        &self.worlds[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut World {
+        &mut self.worlds[self.active]
+    }
+
+    pub fn get(&self, id: usize) -> Option<&World> {
+        self.worlds.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut World> {
+        self.worlds.get_mut(id)
+    }
+
+    /// Freezes the active world and unfreezes world `id`.
+    pub fn activate(&mut self, id: usize) -> Result<(), EcsError> {
+        if id >= self.worlds.len() {
+            return Err(EcsError::UnknownWorld { id });
+        }
+        self.active = id;
+        Ok(())
+    }
+
+    /// Runs the active world's schedule.
+    pub fn run_schedule(&mut self) -> Result<(), EcsError> {
+        self.active_mut().run_schedule()
+    }
+
+    /// Moves `entity` from the active world to world `to`; see
+    /// `World::transfer_entity`. `to` stays frozen until it is activated.
+    pub fn transfer_entity(&mut self, entity: Entity, to: usize) -> Result<Entity, EcsError> {
+        if to >= self.worlds.len() {
+            return Err(EcsError::UnknownWorld { id: to });
+        }
+        if to == self.active {
+            if !self.active().is_alive(entity) {
+                return Err(EcsError::StaleEntity { entity });
+            }
+            return Ok(entity);
+        }
+        let (from, to) = if self.active < to {
+            let (left, right) = self.worlds.split_at_mut(to);
+            (&mut left[self.active], &mut right[0])
+        } else {
+            let (left, right) = self.worlds.split_at_mut(self.active);
+            (&mut right[0], &mut left[to])
+        };
+        from.transfer_entity(entity, to)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ecs::{Component, Stage};
+
+    use super::*;
+
+    struct Turns(pub u32);
+    #[derive(Component)]
+    struct Player;
+
+    fn level() -> World {
+        let mut world = World::new();
+        world.add_resource(Turns(0));
+        world.add_system(Stage::Cleanup, "count_turns", |world: &mut World| {
+            world.get_resource_mut::<Turns>().unwrap().0 += 1;
+        });
+        world
+    }
+
+    fn turns(world: &World) -> u32 {
+        world.resource::<Turns>().unwrap().0
+    }
+
+    #[test]
+    fn only_the_active_world_runs() -> Result<(), EcsError> {
+        let mut levels = Worlds::new(level());
+        let player = levels.active_mut().spawn((Player,))?;
+        let below = levels.add(level());
+        levels.run_schedule()?;
+
+        let player = levels.transfer_entity(player, below)?;
+        assert_eq!(
+            levels.transfer_entity(player, 5),
+            Err(EcsError::UnknownWorld { id: 5 })
+        );
+        levels.activate(below)?;
+        levels.run_schedule()?;
+        levels.run_schedule()?;
+
+        assert_eq!(levels.active_id(), below);
+        assert_eq!(turns(levels.active()), 2);
+        assert_eq!(turns(levels.get(0).unwrap()), 1);
+        assert!(levels.active().has_component::<Player>(player));
+        assert_eq!(levels.get(0).unwrap().query::<&Player>().iter()?.count(), 0);
+
+        // Back up the stairs: the upper world is as it was left.
+        let player = levels.transfer_entity(player, 0)?;
+        levels.activate(0)?;
+        assert_eq!(turns(levels.active()), 1);
+        assert!(levels.active().is_alive(player));
+        assert_eq!(levels.activate(2), Err(EcsError::UnknownWorld { id: 2 }));
+
+        Ok(())
+    }
+}
@@ -1,83 +1,240 @@
-use std::io::{self, Read, Write};
+use std::{
+    cmp::Reverse,
+    fs::File,
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+};
+
+use rand::Rng;
 
 use crate::{
-    components::{Direction, Player, Position, Renderable},
-    ecs::World,
+    components::{Direction, Monster, Name, Player, Position, RenderOrder, Renderable},
+    ecs::{Describe, Entity, EventReader, Resource, SaveError, Stage, World},
     map::Map,
     terminal::clear_screen,
 };
 
+const SAVE_FILE: &str = "concoeur.save";
+const MONSTER_RAWS: &str = include_str!("../raws/monsters.raws");
+
+/// The key pressed this turn, if any.
+#[derive(Debug, Default)]
+pub struct KeyPress(pub Option<u8>);
+
+/// Whether the world inspector is drawn below the map. Toggled with 'i'.
+#[derive(Debug, Default, Resource)]
+#[resource(describe)]
+pub struct ShowInspector(pub bool);
+
+impl Describe for ShowInspector {
+    fn describe(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+/// Sent when 'i' shows or hides the world inspector.
+#[derive(Debug)]
+pub struct InspectorToggled;
+
+/// Sent when an entity tries to move into a solid tile.
+#[derive(Debug)]
+pub struct BumpedWall {
+    pub entity: Entity,
+}
+
 pub fn start_game() {
     let mut stdin = io::stdin().lock();
 
-    clear_screen();
-
-    let world = new_game();
-    draw_world(&world);
-    println!("Raw mode is on. Press 'q' to exit.");
+    let mut world = load_game().unwrap_or_else(new_game);
+    world
+        .run_schedule()
+        .unwrap_or_else(|err| panic!("start_game, {}", err));
 
     // Read input one byte at a time
     let mut buffer = [0; 1];
     while stdin.read(&mut buffer).unwrap() > 0 {
-        match buffer[0] {
-            b'q' => break,
-            b'h' => move_player(Direction { x: 0, y: -1 }, &world),
-            b'y' => move_player(Direction { x: -1, y: -1 }, &world),
-            b'k' => move_player(Direction { x: -1, y: 0 }, &world),
-            b'u' => move_player(Direction { x: -1, y: 1 }, &world),
-            b'l' => move_player(Direction { x: 0, y: 1 }, &world),
-            b'n' => move_player(Direction { x: 1, y: 1 }, &world),
-            b'j' => move_player(Direction { x: 1, y: 0 }, &world),
-            b'b' => move_player(Direction { x: 1, y: -1 }, &world),
-            _ => {}
-        };
-        clear_screen();
-        draw_world(&world);
-        println!("Raw mode is on. Press 'q' to exit.");
+        if buffer[0] == b'q' {
+            break;
+        }
+        if buffer[0] == b's' {
+            save_game(&world);
+            continue;
+        }
+        world.add_resource(KeyPress(Some(buffer[0])));
+        world
+            .run_schedule()
+            .unwrap_or_else(|err| panic!("start_game, {}", err));
     }
 }
 
 fn new_game() -> World {
-    let mut world = World::new();
+    let mut world = setup_world();
     let mut map = Map::new(21, 80);
     // map.generate_random_map();
     map.generate_bsp_map();
+    let player_position = Position { x: 19, y: 69 };
+    let mut floor = map.floor_positions();
+    floor.retain(|position| *position != player_position);
     world.add_resource(map);
-    world.register_component::<Position>();
-    world.register_component::<Renderable>();
-    world.register_component::<Player>();
+
+    let mut rng = rand::thread_rng();
+    for prefab in ["goblin", "goblin", "orc"] {
+        if floor.is_empty() {
+            break;
+        }
+        let position = floor.swap_remove(rng.gen_range(0..floor.len()));
+        world
+            .spawn_prefab(prefab, (position,))
+            .unwrap_or_else(|err| panic!("new_game, {}", err));
+    }
     world
-        .create_entity()
-        .with_component(Position { x: 19, y: 69 })
-        .unwrap_or_else(|err| panic!("new_game, {}", err))
-        .with_component(Renderable { display: '@' })
-        .unwrap_or_else(|err| panic!("new_game, {}", err))
-        .with_component(Player::default())
+        .spawn((
+            player_position,
+            Renderable { display: '@' },
+            RenderOrder(1),
+            Player::default(),
+        ))
         .unwrap_or_else(|err| panic!("new_game, {}", err));
+    world
+}
 
+/// Resumes the saved game, if there is one. A save that can't be read is
+/// reported and a new game started instead.
+fn load_game() -> Option<World> {
+    let loaded = match File::open(SAVE_FILE) {
+        Ok(file) => read_game(BufReader::new(file)),
+        Err(err) if err.kind() == ErrorKind::NotFound => return None,
+        Err(err) => Err(err.into()),
+    };
+    loaded
+        .map_err(|err| eprintln!("Couldn't load {}, starting a new game: {}", SAVE_FILE, err))
+        .ok()
+}
+
+fn read_game(input: impl BufRead) -> Result<World, SaveError> {
+    let mut world = setup_world();
+    world.load(input)?;
+    Ok(world)
+}
+
+fn save_game(world: &World) {
+    let mut file = File::create(SAVE_FILE).unwrap_or_else(|err| panic!("save_game, {}", err));
     world
+        .save(&mut file)
+        .unwrap_or_else(|err| panic!("save_game, {}", err));
+}
+
+fn register_types(world: &mut World) -> Result<(), SaveError> {
+    world.register::<Player>()?;
+    world.register::<Position>()?;
+    world.register::<Renderable>()?;
+    world.register::<Name>()?;
+    world.register::<Monster>()?;
+    world.register::<RenderOrder>()?;
+    world.register_resource::<Map>()?;
+    world.register_resource::<ShowInspector>()
+}
+
+/// Registrations, resources and systems shared by new and loaded games.
+fn setup_world() -> World {
+    let mut world = World::new();
+    register_types(&mut world).unwrap_or_else(|err| panic!("setup_world, {}", err));
+    world
+        .load_prefabs(MONSTER_RAWS.as_bytes())
+        .unwrap_or_else(|err| panic!("setup_world, monsters.raws {}", err));
+
+    world.add_resource(KeyPress::default());
+    world.add_system(Stage::Input, "player_input", player_input);
+    world.add_event::<BumpedWall>();
+    world.add_resource(ShowInspector::default());
+    world.add_event::<InspectorToggled>();
+    let mut toggles = EventReader::<InspectorToggled>::new();
+    world.add_system(Stage::Render, "draw_world", move |world: &mut World| {
+        let toggled = world.read_events(&mut toggles).next().is_some();
+        if toggled || needs_redraw(world) {
+            clear_screen();
+            draw_world(world);
+            println!("Raw mode is on. Press 'q' to exit.");
+            if world.resource::<ShowInspector>().is_ok_and(|show| show.0) {
+                draw_inspector(world);
+            }
+        }
+    });
+    let mut bumps = EventReader::<BumpedWall>::new();
+    world
+        .add_system(Stage::Render, "message_log", move |world: &mut World| {
+            if world.read_events(&mut bumps).next().is_some() {
+                println!("There is a wall in the way.");
+            }
+        })
+        .after("draw_world");
+
+    world
+}
+
+fn player_input(world: &mut World) {
+    let Some(key) = world
+        .get_resource_mut::<KeyPress>()
+        .and_then(|key| key.0.take())
+    else {
+        return;
+    };
+    if key == b'i' {
+        if let Some(show) = world.get_resource_mut::<ShowInspector>() {
+            show.0 = !show.0;
+        }
+        world.send_event(InspectorToggled);
+        return;
+    }
+    let dir = match key {
+        b'h' => Direction { x: 0, y: -1 },
+        b'y' => Direction { x: -1, y: -1 },
+        b'k' => Direction { x: -1, y: 0 },
+        b'u' => Direction { x: -1, y: 1 },
+        b'l' => Direction { x: 0, y: 1 },
+        b'n' => Direction { x: 1, y: 1 },
+        b'j' => Direction { x: 1, y: 0 },
+        b'b' => Direction { x: 1, y: -1 },
+        _ => return,
+    };
+    move_player(dir, world);
+}
+
+/// Whether anything drawn has moved, changed, appeared or disappeared since
+/// the last schedule run. Everything counts as added on the first run.
+fn needs_redraw(world: &World) -> bool {
+    world
+        .query::<Entity>()
+        .changed::<Position>()
+        .iter()
+        .is_ok_and(|mut changed| changed.next().is_some())
+        || world
+            .query::<Entity>()
+            .changed::<Renderable>()
+            .iter()
+            .is_ok_and(|mut changed| changed.next().is_some())
+        || !world.removed::<Position>().is_empty()
+        || !world.removed::<Renderable>().is_empty()
 }
 
 fn draw_world(world: &World) {
-    let mut query = world.query();
-    let query_entities = query
-        .with_component::<Renderable>()
-        .unwrap_or_else(|err| panic!("draw_world, {}", err))
-        .with_component::<Position>()
-        .unwrap_or_else(|err| panic!("draw_world, {}", err))
-        .run_query();
+    // Topmost first, so `find` below picks what is drawn on a shared tile.
+    let query_entities: Vec<_> = world
+        .query::<(&Position, &Renderable)>()
+        .order_by_key::<RenderOrder, _>(|order| Reverse(*order))
+        .iter()
+        .and_then(|items| items.collect())
+        .unwrap_or_else(|err| panic!("draw_world, {}", err));
 
     let map = world.get_resource::<Map>();
     let mut buffer = String::from("");
     if let Some(map) = map {
         map.tiles.iter().enumerate().for_each(|(row_index, row)| {
             row.iter().enumerate().for_each(|(tile_index, tile)| {
-                let found_entity = query_entities.iter().find(|&entity| {
-                    let position = entity.get_component_mut::<Position>().unwrap();
-                    return position.x == row_index && position.y == tile_index;
-                });
-                if let Some(entity) = found_entity {
-                    buffer.push(entity.get_component::<Renderable>().unwrap().display);
+                let found_entity = query_entities
+                    .iter()
+                    .find(|(position, _)| position.x == row_index && position.y == tile_index);
+                if let Some((_, renderable)) = found_entity {
+                    buffer.push(renderable.display);
                 } else {
                     buffer.push(tile.display);
                 }
@@ -91,24 +248,147 @@ fn draw_world(world: &World) {
     }
 }
 
-fn move_player(dir: Direction, world: &World) {
-    let mut query = world.query();
-    let query_entities = query
-        .with_component::<Player>()
-        .unwrap_or_else(|err| panic!("move_player, {}", err))
-        .with_component::<Position>()
-        .unwrap_or_else(|err| panic!("move_player, {}", err))
-        .run_query();
+fn draw_inspector(world: &World) {
+    let mut stdout = std::io::stdout().lock();
+    world
+        .dump(&mut stdout)
+        .unwrap_or_else(|err| panic!("draw_inspector, {}", err));
+    stdout.flush().unwrap();
+}
 
-    let mut position = query_entities[0].get_component_mut::<Position>().unwrap();
+fn move_player(dir: Direction, world: &mut World) {
+    let (entity, bumped) = {
+        let query = world.query::<(Entity, &Player, &Position)>();
+        let Ok(mut players) = query.iter() else {
+            return;
+        };
+        let Some(Ok((entity, _, position))) = players.next() else {
+            return;
+        };
 
-    let map = world.get_resource::<Map>();
-    if let Some(map) = map {
+        let Ok(map) = world.resource::<Map>() else {
+            return;
+        };
         let new_pos = position.add_dir(&dir);
-        if map.tiles[new_pos.x][new_pos.y].is_solid {
-            ()
-        } else {
-            position.add_dir_mut(dir);
+        (entity, map.tiles[new_pos.x][new_pos.y].is_solid)
+    };
+    if bumped {
+        world.send_event(BumpedWall { entity });
+    } else if let Ok(mut position) = world
+        .entity(entity)
+        .and_then(|player| player.get_mut::<Position>())
+    {
+        // Only borrowed mutably here, so bumping a wall doesn't count as a
+        // change to redraw for.
+        position.add_dir_mut(dir);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ecs::{EcsError, StorageKind};
+
+    use super::*;
+
+    #[test]
+    fn saved_game_loads_identically() -> Result<(), SaveError> {
+        let mut world = new_game();
+        let despawned = world.spawn((Position { x: 1, y: 1 },)).unwrap();
+        world.despawn(despawned).unwrap();
+        world
+            .spawn((Position { x: 2, y: 3 }, Renderable { display: 'g' }))
+            .unwrap();
+        // Freed last to first, so slot order isn't the order they're reused.
+        let first = world.spawn((Position { x: 4, y: 1 },)).unwrap();
+        let second = world.spawn((Position { x: 4, y: 2 },)).unwrap();
+        world.despawn(second).unwrap();
+        world.despawn(first).unwrap();
+        let mut saved = vec![];
+        world.save(&mut saved)?;
+
+        let mut loaded = read_game(saved.as_slice())?;
+        let mut resaved = vec![];
+        loaded.save(&mut resaved)?;
+
+        assert_eq!(String::from_utf8(resaved), String::from_utf8(saved));
+        assert_eq!(
+            loaded.get_resource::<Map>().as_deref(),
+            world.get_resource::<Map>().as_deref()
+        );
+        assert!(!loaded.is_alive(despawned));
+        let player_positions: Vec<(usize, usize)> = loaded
+            .query::<(&Player, &Position)>()
+            .iter()?
+            .map(|item| item.map(|(_, position)| (position.x, position.y)))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(player_positions, vec![(19, 69)]);
+        assert_eq!(
+            loaded.storage_kind::<Player>(),
+            Some(StorageKind::SparseSet)
+        );
+
+        for _ in 0..3 {
+            assert_eq!(
+                loaded.spawn((Position { x: 5, y: 5 },)),
+                world.spawn((Position { x: 5, y: 5 },))
+            );
         }
+
+        let corrupt = "concoeur-save 1\nentity 0 0 0\ncomponent Position\n  nowhere\n";
+        assert!(read_game(corrupt.as_bytes()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn new_game_spawns_monsters_from_raws() {
+        let world = new_game();
+        let mut names: Vec<String> = world
+            .query::<(&Monster, &Name, &Position)>()
+            .iter()
+            .unwrap()
+            .map(|item| item.map(|(_, name, _)| name.0.clone()))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        names.sort();
+
+        assert_eq!(names, vec!["Goblin", "Goblin", "Orc"]);
+    }
+
+    #[test]
+    fn bumping_a_wall_leaves_the_player_unchanged() -> Result<(), EcsError> {
+        let mut world = setup_world();
+        let mut map = Map::new(3, 3);
+        map.tiles[1][1].is_solid = false;
+        map.tiles[1][2].is_solid = false;
+        world.add_resource(map);
+        let player = world.spawn((Position { x: 1, y: 1 }, Player::default()))?;
+        world.clear_trackers();
+
+        move_player(Direction { x: -1, y: 0 }, &mut world);
+        assert_eq!(
+            world
+                .query::<Entity>()
+                .changed::<Position>()
+                .iter()?
+                .count(),
+            0
+        );
+
+        move_player(Direction { x: 0, y: 1 }, &mut world);
+        assert_eq!(
+            *world.entity(player)?.get::<Position>()?,
+            Position { x: 1, y: 2 }
+        );
+        assert_eq!(
+            world
+                .query::<Entity>()
+                .changed::<Position>()
+                .iter()?
+                .count(),
+            1
+        );
+
+        Ok(())
     }
 }
@@ -1,3 +1,7 @@
+// Lets `#[derive(Component)]` output, which names `::concoeur`, compile
+// inside this crate too.
+extern crate self as concoeur;
+
 pub mod components;
 pub mod ecs;
 pub mod game;
@@ -1,21 +1,23 @@
 use std::{
     cell::{Ref, RefCell},
-    char,
     rc::Rc,
-    usize,
 };
 
 use rand::Rng;
 
-use crate::components::Position;
+use crate::{
+    components::Position,
+    ecs::{Describe, EcsError, Persist, Resource},
+};
 
-#[derive(Default, Clone, Debug)]
+#[derive(Default, Clone, Debug, PartialEq)]
 pub struct Tile {
     pub display: char,
     pub is_solid: bool,
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, PartialEq, Resource)]
+#[resource(persist, describe)]
 pub struct Map {
     pub tiles: Vec<Vec<Tile>>,
 }
@@ -36,6 +38,20 @@ impl Map {
         }
     }
 
+    /// Positions of every tile that isn't solid.
+    pub fn floor_positions(&self) -> Vec<Position> {
+        self.tiles
+            .iter()
+            .enumerate()
+            .flat_map(|(x, row)| {
+                row.iter()
+                    .enumerate()
+                    .filter(|(_, tile)| !tile.is_solid)
+                    .map(move |(y, _)| Position { x, y })
+            })
+            .collect()
+    }
+
     pub fn generate_bsp_map(&mut self) {
         let mut rng = rand::thread_rng();
         let mut bsp_tree = TreeNode::new(Dimensions {
@@ -68,6 +84,67 @@ impl Map {
     }
 }
 
+/// Saved as the rows of tile displays followed by the same number of rows
+/// marking solid tiles with `#` and open ones with `.`.
+impl Persist for Map {
+    const NAME: &'static str = "Map";
+
+    fn save(&self) -> String {
+        let displays = self
+            .tiles
+            .iter()
+            .map(|row| row.iter().map(|tile| tile.display).collect::<String>());
+        let solids = self.tiles.iter().map(|row| {
+            row.iter()
+                .map(|tile| if tile.is_solid { '#' } else { '.' })
+                .collect::<String>()
+        });
+        displays.chain(solids).collect::<Vec<_>>().join("\n")
+    }
+
+    fn load(value: &str) -> Result<Self, String> {
+        // `lines` rather than `split`, so an empty map has no rows.
+        let lines: Vec<&str> = value.lines().collect();
+        if !lines.len().is_multiple_of(2) {
+            return Err("map needs as many solid rows as display rows".to_string());
+        }
+        let (displays, solids) = lines.split_at(lines.len() / 2);
+        let tiles = displays
+            .iter()
+            .zip(solids)
+            .map(|(displays, solids)| {
+                if displays.chars().count() != solids.chars().count() {
+                    return Err("map row and its solid row differ in length".to_string());
+                }
+                displays
+                    .chars()
+                    .zip(solids.chars())
+                    .map(|(display, solid)| match solid {
+                        '#' => Ok(Tile {
+                            display,
+                            is_solid: true,
+                        }),
+                        '.' => Ok(Tile {
+                            display,
+                            is_solid: false,
+                        }),
+                        _ => Err(format!("invalid solid marker {:?}", solid)),
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Map { tiles })
+    }
+}
+
+/// Just the size; the map itself is on screen anyway.
+impl Describe for Map {
+    fn describe(&self) -> String {
+        let width = self.tiles.first().map_or(0, Vec::len);
+        format!("{}x{}", self.tiles.len(), width)
+    }
+}
+
 #[derive(Debug)]
 struct Dimensions {
     pub start: Position,
@@ -87,55 +164,67 @@ struct TreeNode {
 
 impl TreeNode {
     pub fn new(dims: Dimensions) -> Self {
-        return TreeNode {
+        TreeNode {
             space: dims,
             room: None,
             left: None,
             right: None,
-        };
+        }
     }
 
-    fn check_node(&self, node: &TreeNode) -> Result<(), &'static str> {
+    fn check_node(&self, node: &TreeNode) -> Result<(), EcsError> {
         if node.space.start.x < self.space.start.x || node.space.start.y < self.space.start.y {
-            return Err("Invalid start position, before bounding box");
+            return Err(EcsError::InvalidNode {
+                reason: "start position before bounding box",
+            });
         }
         if node.space.start.x > self.space.start.x + self.space.height
             || node.space.start.y > self.space.start.y + self.space.width
         {
-            return Err("Invalid start position, past bounding box");
+            return Err(EcsError::InvalidNode {
+                reason: "start position past bounding box",
+            });
         }
         if node.space.width > self.space.width {
-            return Err("Node width is too large");
+            return Err(EcsError::InvalidNode {
+                reason: "node width is too large",
+            });
         }
         if node.space.height > self.space.height {
-            return Err("Node height is too large");
+            return Err(EcsError::InvalidNode {
+                reason: "node height is too large",
+            });
         }
         if node.space.start.y + node.space.width > self.space.start.y + self.space.width {
-            return Err("Node extends past parent width");
+            return Err(EcsError::InvalidNode {
+                reason: "node extends past parent width",
+            });
         }
         if node.space.start.x + node.space.height > self.space.start.x + self.space.height {
-            return Err("Node extends past parent height");
+            return Err(EcsError::InvalidNode {
+                reason: "node extends past parent height",
+            });
         }
         Ok(())
     }
 
-    pub fn insert_left(&mut self, node: TreeNode) -> Result<(), &'static str> {
+    pub fn insert_left(&mut self, node: TreeNode) -> Result<(), EcsError> {
         self.check_node(&node)?;
         self.left = Some(Rc::new(RefCell::new(node)));
 
         Ok(())
     }
 
-    pub fn insert_right(&mut self, node: TreeNode) -> Result<(), &'static str> {
+    pub fn insert_right(&mut self, node: TreeNode) -> Result<(), EcsError> {
         self.check_node(&node)?;
         self.right = Some(Rc::new(RefCell::new(node)));
 
         Ok(())
     }
 
-    pub fn get_children(&self) -> (Option<Ref<TreeNode>>, Option<Ref<TreeNode>>) {
-        let left: Option<Ref<TreeNode>>;
-        let right: Option<Ref<TreeNode>>;
+    pub fn get_children(&self) -> (Option<Ref<'_, TreeNode>>, Option<Ref<'_, TreeNode>>) {
+        let left: Option<Ref<'_, TreeNode>>;
+        let right: Option<Ref<'_, TreeNode>>;
         if let Some(node) = &self.left {
             left = Some(node.borrow());
         } else {
@@ -156,7 +245,7 @@ impl TreeNode {
         let min_width = self.space.width / 2;
         let min_height = self.space.height / 2;
 
-        if self.space.width - 1 - min_width <= 0 || self.space.height - 1 - min_height <= 0 {
+        if self.space.width - 1 - min_width == 0 || self.space.height - 1 - min_height == 0 {
             return;
         }
 
@@ -184,66 +273,6 @@ impl TreeNode {
     }
 }
 
-#[cfg(test)]
-mod test {
-    use super::TreeNode;
-    use crate::{components::Position, map::Dimensions};
-
-    #[test]
-    fn create_new_tree() {
-        let tree_root = TreeNode::new(Dimensions {
-            start: Position { x: 0, y: 0 },
-            width: 10,
-            height: 10,
-        });
-        assert_eq!(tree_root.space.start.x, 0);
-        assert_eq!(tree_root.space.start.y, 0);
-        assert_eq!(tree_root.space.width, 10);
-        assert_eq!(tree_root.space.height, 10);
-
-        let (left, right) = &tree_root.get_children();
-        assert!(left.is_none());
-        assert!(right.is_none());
-    }
-
-    #[test]
-    fn insert_into_root() -> Result<(), &'static str> {
-        let mut tree_root = TreeNode::new(Dimensions {
-            start: Position { x: 0, y: 0 },
-            width: 100,
-            height: 100,
-        });
-
-        tree_root.insert_left(TreeNode::new(Dimensions {
-            start: Position { x: 0, y: 0 },
-            width: 50,
-            height: 100,
-        }))?;
-
-        tree_root.insert_right(TreeNode::new(Dimensions {
-            start: Position { x: 0, y: 50 },
-            width: 50,
-            height: 100,
-        }))?;
-
-        let (left, right) = tree_root.get_children();
-
-        let left_val = &left.unwrap().space;
-        assert_eq!(left_val.start.x, 0);
-        assert_eq!(left_val.start.y, 0);
-        assert_eq!(left_val.width, 50);
-        assert_eq!(left_val.height, 100);
-
-        let right_val = &right.unwrap().space;
-        assert_eq!(right_val.start.x, 0);
-        assert_eq!(right_val.start.y, 50);
-        assert_eq!(right_val.width, 50);
-        assert_eq!(right_val.height, 100);
-
-        Ok(())
-    }
-}
-
 fn split_bsp_tree_node(node: &mut TreeNode, vertical: bool) -> &mut TreeNode {
     if node.space.width <= 10 || node.space.height <= 10 {
         node.add_room();
@@ -321,8 +350,8 @@ fn draw_rooms(node: &TreeNode, tiles: &mut Vec<Vec<Tile>>) {
             is_solid: true,
             display: '┌',
         };
-        for tile_index in start_y + 1..end_y - 1 {
-            tiles[start_x][tile_index] = Tile {
+        for tile in &mut tiles[start_x][start_y + 1..end_y - 1] {
+            *tile = Tile {
                 is_solid: true,
                 display: '─',
             }
@@ -331,18 +360,18 @@ fn draw_rooms(node: &TreeNode, tiles: &mut Vec<Vec<Tile>>) {
             is_solid: true,
             display: '┐',
         };
-        for row_index in start_x + 1..end_x - 1 {
-            tiles[row_index][start_y] = Tile {
+        for row in &mut tiles[start_x + 1..end_x - 1] {
+            row[start_y] = Tile {
                 is_solid: true,
                 display: '│',
             };
-            for tile_index in start_y + 1..end_y - 1 {
-                tiles[row_index][tile_index] = Tile {
+            for tile in &mut row[start_y + 1..end_y - 1] {
+                *tile = Tile {
                     is_solid: false,
                     display: '.',
                 }
             }
-            tiles[row_index][end_y - 1] = Tile {
+            row[end_y - 1] = Tile {
                 is_solid: true,
                 display: '│',
             };
@@ -351,8 +380,8 @@ fn draw_rooms(node: &TreeNode, tiles: &mut Vec<Vec<Tile>>) {
             is_solid: true,
             display: '└',
         };
-        for tile_index in start_y + 1..end_y - 1 {
-            tiles[end_x - 1][tile_index] = Tile {
+        for tile in &mut tiles[end_x - 1][start_y + 1..end_y - 1] {
+            *tile = Tile {
                 is_solid: true,
                 display: '─',
             }
@@ -364,10 +393,103 @@ fn draw_rooms(node: &TreeNode, tiles: &mut Vec<Vec<Tile>>) {
         return;
     }
     let (left, right) = node.get_children();
-    if left.is_some() {
-        draw_rooms(&left.unwrap(), tiles);
+    if let Some(left) = left {
+        draw_rooms(&left, tiles);
     }
-    if right.is_some() {
-        draw_rooms(&right.unwrap(), tiles);
+    if let Some(right) = right {
+        draw_rooms(&right, tiles);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Map, TreeNode};
+    use crate::ecs::{EcsError, Persist};
+    use crate::{components::Position, map::Dimensions};
+
+    #[test]
+    fn create_new_tree() {
+        let tree_root = TreeNode::new(Dimensions {
+            start: Position { x: 0, y: 0 },
+            width: 10,
+            height: 10,
+        });
+        assert_eq!(tree_root.space.start.x, 0);
+        assert_eq!(tree_root.space.start.y, 0);
+        assert_eq!(tree_root.space.width, 10);
+        assert_eq!(tree_root.space.height, 10);
+
+        let (left, right) = &tree_root.get_children();
+        assert!(left.is_none());
+        assert!(right.is_none());
+    }
+
+    #[test]
+    fn insert_into_root() -> Result<(), EcsError> {
+        let mut tree_root = TreeNode::new(Dimensions {
+            start: Position { x: 0, y: 0 },
+            width: 100,
+            height: 100,
+        });
+
+        tree_root.insert_left(TreeNode::new(Dimensions {
+            start: Position { x: 0, y: 0 },
+            width: 50,
+            height: 100,
+        }))?;
+
+        tree_root.insert_right(TreeNode::new(Dimensions {
+            start: Position { x: 0, y: 50 },
+            width: 50,
+            height: 100,
+        }))?;
+
+        let (left, right) = tree_root.get_children();
+
+        let left_val = &left.unwrap().space;
+        assert_eq!(left_val.start.x, 0);
+        assert_eq!(left_val.start.y, 0);
+        assert_eq!(left_val.width, 50);
+        assert_eq!(left_val.height, 100);
+
+        let right_val = &right.unwrap().space;
+        assert_eq!(right_val.start.x, 0);
+        assert_eq!(right_val.start.y, 50);
+        assert_eq!(right_val.width, 50);
+        assert_eq!(right_val.height, 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn insert_outside_parent_fails() {
+        let mut tree_root = TreeNode::new(Dimensions {
+            start: Position { x: 0, y: 0 },
+            width: 100,
+            height: 100,
+        });
+
+        let result = tree_root.insert_left(TreeNode::new(Dimensions {
+            start: Position { x: 0, y: 60 },
+            width: 50,
+            height: 100,
+        }));
+        assert_eq!(
+            result,
+            Err(EcsError::InvalidNode {
+                reason: "node extends past parent width"
+            })
+        );
+        assert!(tree_root.get_children().0.is_none());
+    }
+
+    #[test]
+    fn map_save_round_trip() {
+        let mut map = Map::new(21, 80);
+        map.generate_bsp_map();
+
+        assert_eq!(Map::load(&map.save()), Ok(map));
+        assert!(Map::load("#.\n.").is_err());
+        assert_eq!(Map::load(&Map::default().save()), Ok(Map::default()));
     }
 }
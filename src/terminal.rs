@@ -13,15 +13,7 @@ pub fn enter_raw_mode() -> impl FnOnce() {
     hide_cursor();
 
     // Create a termios structure to hold the terminal attributes
-    let mut termios = termios {
-        c_iflag: 0,
-        c_oflag: 0,
-        c_cflag: 0,
-        c_lflag: 0,
-        c_cc: [0; libc::NCCS],
-        c_ispeed: 0,
-        c_ospeed: 0,
-    };
+    let mut termios: termios = unsafe { std::mem::zeroed() };
 
     // Get the current terminal attributes
     unsafe {
@@ -41,14 +33,12 @@ pub fn enter_raw_mode() -> impl FnOnce() {
         tcsetattr(fd, TCSANOW, &termios);
     }
 
-    let restore_fn = move || {
+    move || {
         // Restore the original terminal attributes
         unsafe {
             tcsetattr(fd, TCSANOW, &original_termios);
         }
-    };
-
-    restore_fn
+    }
 }
 
 pub fn clear_screen() {